@@ -50,10 +50,66 @@ pub struct AppArgs {
     #[clap(long, parse(from_flag))]
     pub do_not_update_speakers_list: bool,
 
-    /// 指定订阅API的官方订阅密钥以及地域， 可添加多个，遍历使用，格式：{subscribe_key},{region}   例： --subscribe-key 956d0b8cb34e4kb1b9cb8c614d313ae3,southeastasia
+    /// 发音人列表缓存目录，每个接口及地域的发音人列表获取成功后保存到该目录，获取失败或指定了 --do-not-update-speakers-list 时使用
+    #[clap(long, value_name = "path", default_value_t = format ! ("{}/tts-server/voices", std::env::temp_dir().to_str().unwrap()))]
+    pub voices_cache_dir: String,
+
+    /// 定时刷新发音人列表的间隔，单位为小时，为 0 时不刷新
+    #[clap(long, value_name = "hours", default_value_t = 24)]
+    pub voices_refresh_interval: u64,
+
+    /// 指定订阅API的官方订阅密钥以及地域， 可添加多个，遍历使用，格式：{subscribe_key},{region}[,{每月字符额度}]   例： --subscribe-key 956d0b8cb34e4kb1b9cb8c614d313ae3,southeastasia,500000
     #[clap(long)]
     pub subscribe_key: Vec<String>,
 
+    /// 订阅key默认的每月字符额度，订阅key中未指定额度时使用，为 0 时不限制。优先使用剩余额度足够的key，所有key额度不足时拒绝请求
+    #[clap(long, value_name = "chars", default_value_t = 0)]
+    pub subscribe_key_monthly_chars: u64,
+
+    /// 订阅key每月字符用量的保存路径，默认保存在程序运行目录下，程序退出时会立即保存
+    #[clap(long, value_name = "path", default_value_t = String::from("subscribe_usage.json"))]
+    pub subscribe_usage_file: String,
+
+    /// 长文本分段合成时每段的最大字符数，超出后会在段落或句子处切分，分段合成后再拼接音频
+    #[clap(long, value_name = "len", default_value_t = 1000)]
+    pub text_chunk_len: usize,
+
+    /// 等待微软接口完整响应的超时时间，单位为秒，超时的请求会返回 504
+    #[clap(long, value_name = "secs", default_value_t = 60)]
+    pub request_timeout: u64,
+
+    /// 每个接口 (每个订阅key) 同时保持的 websocket 连接数，请求会分配到进行中请求最少的连接
+    #[clap(long, value_name = "num", default_value_t = 1)]
+    pub ws_pool_size: usize,
+
+    /// 单个 websocket 连接上同时进行的最大请求数，所有连接都达到上限时新请求排队等待
+    #[clap(long, value_name = "num", default_value_t = 10)]
+    pub ws_max_in_flight: usize,
+
+    /// websocket 连接发送 ping 的间隔，单位为秒，为 0 时不发送 ping 也不在后台重建连接
+    #[clap(long, value_name = "secs", default_value_t = 30)]
+    pub ws_ping_interval: u64,
+
+    /// websocket 连接空闲超过该时长后在后台重新建立连接，单位为秒，为 0 时不主动重建
+    #[clap(long, value_name = "secs", default_value_t = 150)]
+    pub ws_idle_lifetime: u64,
+
+    /// 发音词典文件路径，json 格式，按语言配置词语的音标或替换文本，例：{"zh-CN": [{"grapheme": "TTS", "alias": "文本转语音"}]}
+    #[clap(long, value_name = "path")]
+    pub lexicon_file: Option<String>,
+
+    /// 文本规则文件路径，json 格式，可按语言配置正则替换、文本替换以及跳过规则，文件修改后自动重新加载
+    #[clap(long, value_name = "path")]
+    pub text_rule_file: Option<String>,
+
+    /// 发音人预设文件路径，json 格式，请求中通过 preset 参数使用，文件修改后自动重新加载，例：{"narrator": {"informant": "zh-CN-YunxiNeural", "style": "narration-relaxed", "rate": 1.1}}
+    #[clap(long, value_name = "path")]
+    pub voice_preset_file: Option<String>,
+
+    /// 严格校验请求参数，发音人、风格、音频格式等参数不合法时返回 400 及可用值，而不是使用默认值，请求中的 strict 参数优先
+    #[clap(long, parse(from_flag))]
+    pub strict_validation: bool,
+
     /// 是否启用 webUI
     #[clap(long, parse(from_flag))]
     pub web_ui: bool,
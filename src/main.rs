@@ -22,7 +22,15 @@ use utils::log::init_log;
 
 use crate::{
     cmd::AppArgs,
-    utils::{azure_api::MS_TTS_QUALITY_LIST, random_string},
+    utils::{
+        azure_api::MS_TTS_QUALITY_LIST,
+        lexicon::init_lexicon,
+        quota::{flush_subscribe_usage, init_subscribe_usage},
+        random_string,
+        text_rule::init_text_rule,
+        voice_cache::init_voices_list_refresh,
+        voice_preset::init_voice_preset,
+    },
 };
 
 pub(crate) static GLOBAL_EB: Lazy<Arc<EventBus<VertxMessage>>> = Lazy::new(|| {
@@ -50,9 +58,15 @@ async fn main_async() -> Result<()> {
     }
     //
     info!("准备启动，程序参数: {:?}", args);
+    init_lexicon();
+    init_text_rule();
+    init_voice_preset();
+    init_subscribe_usage();
     GLOBAL_EB.start().await;
     ms_tts::register_service().await;
+    init_voices_list_refresh();
     web::register_service().await;
+    flush_subscribe_usage();
     info!("谢谢使用，希望能收到您对软件的看法和建议！");
     Ok(())
 }
@@ -1,37 +1,49 @@
 use std::{
     collections::HashMap,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
 
+use backon::{ExponentialBuilder, Retryable};
 use bytes::{BufMut, Bytes, BytesMut};
 use event_bus::message::IMessage;
 use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tokio::{
     net::TcpStream,
-    sync::{Mutex, OnceCell},
-    time::sleep,
+    sync::{watch, Mutex, OnceCell, OwnedSemaphorePermit, Semaphore},
+    time::{sleep, Instant},
 };
 use tokio_native_tls::TlsStream;
-use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+    WebSocketStream,
+};
 
 use crate::{
+    error::TTSServerError,
     utils::{
+        audio::{audio_duration_ticks, concat_audio},
         azure_api::{
             AzureApiEdgeFree, AzureApiGenerateXMML, AzureApiNewWebsocket, AzureApiRegionIdentifier,
             AzureApiSpeakerList, AzureApiSubscribeToken, AzureSubscribeKey, MsTtsMsgRequest,
             VoicesList,
         },
         binary_search,
+        lexicon::apply_lexicon,
+        quota::{refund_subscribe_usage, try_reserve_subscribe_usage},
+        random_string,
+        ssml::escape_xml,
+        text::split_text,
     },
     AppArgs,
 };
@@ -41,6 +53,9 @@ pub(crate) static TAG_BODY_SPLIT: [u8; 12] = [80, 97, 116, 104, 58, 97, 117, 100
 // gX-R
 pub(crate) static TAG_NONE_DATA_START: [u8; 2] = [0, 103];
 
+/// 单个 SSML 文档中最多包含的 voice 元素数量
+pub(crate) const MS_TTS_DIALOGUE_MAX_VOICES: usize = 50;
+
 impl MsTtsMsgRequest {
     #[inline]
     pub fn to_bytes(&self) -> Bytes {
@@ -52,6 +67,128 @@ impl MsTtsMsgRequest {
         let data: Self = bincode::deserialize(&bytes[..]).unwrap();
         data
     }
+
+    ///
+    /// 生成 SSML 中的文本，转义后再应用发音词典
+    pub fn ssml_text(&self) -> String {
+        apply_lexicon(&escape_xml(&self.text), &self.informant, &self.phoneme_list)
+    }
+
+    ///
+    /// 生成 prosody 元素的属性
+    pub fn prosody_attributes(&self) -> String {
+        let mut attributes = format!(
+            "rate='{}%' pitch='{}' volume='{}'",
+            self.rate,
+            self.pitch,
+            self.volume.as_deref().unwrap_or("+0%")
+        );
+        if let Some(contour) = &self.contour {
+            attributes.push_str(format!(" contour='{}'", contour).as_str());
+        }
+        attributes
+    }
+
+    ///
+    /// 计费字符数，完整 SSML 文档按文档长度计算，否则按各片段文本长度计算
+    pub fn billing_chars(&self) -> u64 {
+        if let Some(ssml) = &self.ssml {
+            return ssml.chars().count() as u64;
+        }
+        if self.segments.is_empty() {
+            self.text.chars().count() as u64
+        } else {
+            self.segments.iter().map(|i| i.billing_chars()).sum()
+        }
+    }
+
+    ///
+    /// 发送前需要预留的字符数，断开重试的请求在首次发送时已预留，不再重复计算
+    pub fn reserve_chars(&self) -> u64 {
+        if self.is_retry {
+            0
+        } else {
+            self.billing_chars()
+        }
+    }
+
+    ///
+    /// 取出按语种切分的片段，未切分时返回自身
+    pub fn into_segments(mut self) -> Vec<Self> {
+        if self.segments.is_empty() {
+            vec![self]
+        } else {
+            std::mem::take(&mut self.segments)
+        }
+    }
+
+    ///
+    /// 按语种片段及文本长度切分请求，`multi_voice` 为 true 时多个片段合并为包含多个 voice 元素的 SSML 文档
+    pub fn split_chunks(self, multi_voice: bool, max_len: usize) -> Vec<Self> {
+        let list = self.into_segments();
+        if multi_voice && list.len() > 1 {
+            Self::merge_dialogue(list, max_len)
+        } else {
+            list.into_iter()
+                .flat_map(|i| i.split_by_text_len(max_len))
+                .collect()
+        }
+    }
+
+    /// 按文本长度切分请求，切分后的每段请求使用新的请求id，SSML 请求不切分
+    pub fn split_by_text_len(self, max_len: usize) -> Vec<Self> {
+        if self.ssml.is_some() {
+            return vec![self];
+        }
+        let text_list = split_text(&self.text, max_len);
+        if text_list.len() <= 1 {
+            return vec![self];
+        }
+        text_list
+            .into_iter()
+            .map(|text| MsTtsMsgRequest {
+                text,
+                request_id: random_string(32),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    ///
+    /// 合并多人对话片段，按顺序将片段放入同一个 SSML 文档的多个 voice 元素中
+    ///
+    /// 每个文档的文本总长度不超过 `max_len`，超出后使用新的文档，过长的片段会先按长度切分
+    pub fn merge_dialogue(list: Vec<Self>, max_len: usize) -> Vec<Self> {
+        let mut result = Vec::new();
+        let mut batch: Vec<Self> = Vec::new();
+        let mut batch_len = 0;
+        for segment in list.into_iter().flat_map(|i| i.split_by_text_len(max_len)) {
+            let len = segment.text.chars().count();
+            if !batch.is_empty()
+                && (batch_len + len > max_len || batch.len() >= MS_TTS_DIALOGUE_MAX_VOICES)
+            {
+                result.push(Self::from_dialogue_batch(std::mem::take(&mut batch)));
+                batch_len = 0;
+            }
+            batch_len += len;
+            batch.push(segment);
+        }
+        if !batch.is_empty() {
+            result.push(Self::from_dialogue_batch(batch));
+        }
+        result
+    }
+
+    fn from_dialogue_batch(batch: Vec<Self>) -> Self {
+        let ssml = AzureApiSubscribeToken::generate_ssml(&batch);
+        let first = batch.into_iter().next().unwrap();
+        MsTtsMsgRequest {
+            text: String::new(),
+            request_id: random_string(32),
+            ssml: Some(ssml),
+            ..first
+        }
+    }
 }
 
 impl From<MsTtsMsgRequest> for Vec<u8> {
@@ -68,11 +205,118 @@ impl From<MsTtsMsgRequest> for event_bus::message::Body {
     }
 }
 
+/// 文本边界事件类型
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MsTtsBoundaryType {
+    Word,
+    Sentence,
+}
+
+/// 文本边界事件，偏移及时长单位为 100 纳秒
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct MsTtsBoundary {
+    pub boundary_type: MsTtsBoundaryType,
+    /// 在音频中的偏移
+    pub offset: u64,
+    /// 持续时长
+    pub duration: u64,
+    /// 对应文本
+    pub text: String,
+}
+
+/// 微软接口 audio.metadata 消息体
+#[derive(Deserialize, Debug)]
+struct MsTtsMetadata {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<MsTtsMetadataItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MsTtsMetadataItem {
+    #[serde(rename = "Type")]
+    metadata_type: String,
+    #[serde(rename = "Data")]
+    data: Option<MsTtsMetadataData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MsTtsMetadataData {
+    #[serde(rename = "Offset", default)]
+    offset: u64,
+    #[serde(rename = "Duration", default)]
+    duration: u64,
+    #[serde(rename = "text")]
+    text: Option<MsTtsMetadataText>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MsTtsMetadataText {
+    #[serde(rename = "Text", default)]
+    text: String,
+}
+
+impl MsTtsBoundary {
+    ///
+    /// 解析 Path:audio.metadata 消息中的边界事件
+    pub(crate) fn from_metadata_message(msg: &str) -> Vec<Self> {
+        let body = match msg.find("\r\n\r\n") {
+            Some(index) => &msg[index + 4..],
+            None => return Vec::new(),
+        };
+        let metadata: MsTtsMetadata = match serde_json::from_str(body) {
+            Ok(d) => d,
+            Err(e) => {
+                debug!("解析 audio.metadata 失败: {:?}", e);
+                return Vec::new();
+            }
+        };
+        metadata
+            .metadata
+            .into_iter()
+            .filter_map(|item| {
+                let boundary_type = match item.metadata_type.as_str() {
+                    "WordBoundary" => MsTtsBoundaryType::Word,
+                    "SentenceBoundary" => MsTtsBoundaryType::Sentence,
+                    _ => return None,
+                };
+                let data = item.data?;
+                Some(MsTtsBoundary {
+                    boundary_type,
+                    offset: data.offset,
+                    duration: data.duration,
+                    text: data.text.map(|i| i.text).unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// 合成失败原因
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub enum MsTtsMsgError {
+    /// 超时未收到微软接口的完整响应
+    Timeout,
+    /// websocket 连接断开
+    Disconnected,
+    /// websocket 连接断开，且流式响应已输出部分音频，无法重试
+    Interrupted,
+    /// 连续连接失败，接口暂时不可用
+    Unavailable,
+    /// 建立连接失败，仅在不等待重试时返回
+    ConnectFailed(String),
+    /// 所有订阅 key 本月剩余的字符额度都不足
+    QuotaExceeded,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct MsTtsMsgResponse {
     pub request_id: String,
     pub data: Vec<u8>,
     pub file_type: String,
+    /// 单词及句子边界事件，需要在请求中开启
+    pub boundaries: Vec<MsTtsBoundary>,
+    /// 合成失败时的错误信息
+    pub error: Option<MsTtsMsgError>,
 }
 
 impl MsTtsMsgResponse {
@@ -97,6 +341,51 @@ impl MsTtsMsgResponse {
         let data: Self = bincode::deserialize(&bytes[..]).unwrap();
         data
     }
+
+    /// 合成失败的响应
+    pub fn failure(request_id: String, error: MsTtsMsgError) -> Self {
+        MsTtsMsgResponse {
+            request_id,
+            data: Vec::new(),
+            file_type: String::new(),
+            boundaries: Vec::new(),
+            error: Some(error),
+        }
+    }
+
+    /// 按顺序拼接分段请求的响应，后续分段的边界事件偏移会加上之前分段的音频时长
+    pub fn concat(request_id: String, quality: &str, list: Vec<Self>) -> Self {
+        let file_type = list
+            .first()
+            .map(|i| i.file_type.clone())
+            .unwrap_or_default();
+        let mut boundaries = Vec::new();
+        let mut offset = 0;
+        let mut data_list = Vec::with_capacity(list.len());
+        for item in list {
+            boundaries.extend(item.boundaries.iter().map(|b| MsTtsBoundary {
+                offset: b.offset + offset,
+                ..b.clone()
+            }));
+            // 无法根据音频格式计算时长时，使用最后一个边界事件的结束时间
+            offset += audio_duration_ticks(quality, &item.data).unwrap_or_else(|| {
+                item.boundaries
+                    .iter()
+                    .map(|b| b.offset + b.duration)
+                    .max()
+                    .unwrap_or(0)
+            });
+            data_list.push(item.data);
+        }
+        let data = concat_audio(quality, data_list);
+        MsTtsMsgResponse {
+            request_id,
+            data,
+            file_type,
+            boundaries,
+            error: None,
+        }
+    }
 }
 
 type WebsocketRt = SplitSink<WebSocketStream<TlsStream<TcpStream>>, Message>;
@@ -105,6 +394,292 @@ pub struct MsTtsCache {
     pub data: BytesMut,
     pub reply: IMessage,
     pub file_type: Option<String>,
+    pub boundaries: Vec<MsTtsBoundary>,
+    /// 流式响应通道，存在时音频数据不再缓存，收到后直接发送
+    pub stream: Option<UnboundedSender<Bytes>>,
+    /// 超过该时间仍未完成的请求会被清理
+    pub deadline: Instant,
+    /// 原始请求，连接断开后用于重新发送
+    pub request: MsTtsMsgRequest,
+    /// 发送该请求的连接池占用凭证，请求结束时释放
+    pub permit: MsSocketPermit,
+    /// 发送该请求时的连接代数
+    pub generation: usize,
+}
+
+type MsTtsCacheDb = Arc<Mutex<HashMap<String, Arc<Mutex<MsTtsCache>>>>>;
+
+/// 连接断开后请求的最大重试次数
+const MS_TTS_DISCONNECT_RETRIES: usize = 3;
+
+///
+/// 处理断开连接上未完成的请求
+///
+/// 未输出过音频的请求会通过事件总线重新发送，由消费者建立新连接，重试次数用尽后回复断开错误
+async fn retry_disconnected_requests(
+    api_name: &'static str,
+    list: Vec<(String, Arc<Mutex<MsTtsCache>>)>,
+) {
+    for (id, cache) in list {
+        let cache = cache.lock().await;
+        let streamed = cache.stream.is_some() && cache.file_type.is_some();
+        if streamed || cache.request.is_retry {
+            // 重试请求由发起重试的任务统一处理
+            let error = if streamed {
+                MsTtsMsgError::Interrupted
+            } else {
+                MsTtsMsgError::Disconnected
+            };
+            let body = MsTtsMsgResponse::failure(id, error);
+            cache.reply.reply(body.to_vec().into()).await;
+            continue;
+        }
+
+        let mut request = cache.request.clone();
+        request.is_retry = true;
+        let stream = cache.stream.clone();
+        let reply = cache.reply.clone();
+        tokio::spawn(async move {
+            let resp = (|| async {
+                if let Some(stream) = stream.as_ref() {
+                    MS_TTS_STREAM_SENDER
+                        .lock()
+                        .await
+                        .insert(id.clone(), stream.clone());
+                }
+                let resp = crate::GLOBAL_EB
+                    .request(api_name, request.clone().into())
+                    .await;
+                remove_stream(&id).await;
+                let resp = match resp {
+                    Some(data) => MsTtsMsgResponse::from_vec(data.as_bytes().unwrap().to_vec()),
+                    None => MsTtsMsgResponse::failure(id.clone(), MsTtsMsgError::Disconnected),
+                };
+                if resp.error == Some(MsTtsMsgError::Disconnected) {
+                    Err(resp)
+                } else {
+                    Ok(resp)
+                }
+            })
+            .retry(&ExponentialBuilder::default().with_max_times(MS_TTS_DISCONNECT_RETRIES))
+            .notify(|_, dur| warn!("连接断开，{:?} 后重新发送请求: {}", dur, id))
+            .await;
+            let body = match resp {
+                Ok(body) | Err(body) => body,
+            };
+            reply.reply(body.to_vec().into()).await;
+        });
+    }
+}
+
+///
+/// 从缓存中取出截止时间不晚于 `now` 的请求
+pub(crate) async fn take_expired<T>(
+    cache_db: &Mutex<HashMap<String, Arc<Mutex<T>>>>,
+    now: Instant,
+    deadline: fn(&T) -> Instant,
+) -> Vec<(String, Arc<Mutex<T>>)> {
+    let mut db = cache_db.lock().await;
+    let mut expired_id = Vec::new();
+    for (id, cache) in db.iter() {
+        if deadline(&*cache.lock().await) <= now {
+            expired_id.push(id.clone());
+        }
+    }
+    expired_id
+        .into_iter()
+        .filter_map(|id| db.remove(&id).map(|cache| (id, cache)))
+        .collect()
+}
+
+///
+/// 定时清理超时未完成的请求，并回复超时错误
+fn spawn_cache_sweeper(cache_db: MsTtsCacheDb) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let expired = take_expired(&cache_db, Instant::now(), |i| i.deadline).await;
+            for (id, cache) in expired {
+                warn!("请求超时，未收到微软接口的完整响应: {}", id);
+                let cache = cache.lock().await;
+                let body = MsTtsMsgResponse::failure(id, MsTtsMsgError::Timeout);
+                cache.reply.reply(body.to_vec().into()).await;
+            }
+        }
+    });
+}
+
+/// 流式响应 等待 websocket 消费者领取的音频数据通道
+static MS_TTS_STREAM_SENDER: Lazy<Mutex<HashMap<String, UnboundedSender<Bytes>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+///
+/// 注册流式响应，返回该请求id 的音频数据接收通道
+///
+/// 请求结束后通道关闭，回复的 [MsTtsMsgResponse] 中不再包含音频数据
+pub(crate) async fn register_stream(request_id: &str) -> UnboundedReceiver<Bytes> {
+    let (tx, rx) = unbounded();
+    MS_TTS_STREAM_SENDER
+        .lock()
+        .await
+        .insert(request_id.to_owned(), tx);
+    rx
+}
+
+///
+/// 移除未被领取的流式响应通道
+pub(crate) async fn remove_stream(request_id: &str) {
+    MS_TTS_STREAM_SENDER.lock().await.remove(request_id);
+}
+
+/// 建立连接失败后的最短等待时间
+pub(crate) const MS_SOCKET_BACKOFF_MIN: Duration = Duration::from_millis(500);
+/// 建立连接失败后的最长等待时间
+pub(crate) const MS_SOCKET_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// 建立连接的超时时间
+const MS_SOCKET_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// 连续连接失败达到该次数后熔断
+pub(crate) const MS_SOCKET_MAX_FAILURES: usize = 5;
+/// 熔断持续时间，期间请求直接失败，结束后允许一次连接尝试
+pub(crate) const MS_SOCKET_CIRCUIT_COOLDOWN: Duration = Duration::from_secs(30);
+
+///
+/// websocket 连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsSocketState {
+    /// 未连接
+    Disconnected,
+    /// 正在建立连接
+    Connecting,
+    /// 已连接
+    Connected,
+    /// 连接失败，等待到指定时间后重试
+    Backoff(Instant),
+    /// 连续失败次数过多，熔断到指定时间
+    Failed(Instant),
+}
+
+///
+/// 连接池中的单个 websocket 连接
+#[derive(Debug)]
+pub struct MsSocketConnection {
+    tx: Arc<Mutex<Option<WebsocketRt>>>,
+    state: watch::Sender<MsSocketState>,
+    /// 连续连接失败次数
+    failures: AtomicUsize,
+    /// 该连接上进行中的请求数
+    in_flight: AtomicUsize,
+    /// 连接代数，每次建立或替换连接时递增，用于区分新旧连接上的请求
+    generation: AtomicUsize,
+    /// 最后一次请求开始或结束的时间
+    last_active: std::sync::Mutex<Instant>,
+    /// 程序配置的订阅 key 的 hash，连接异常断开时将该 key 标记为不可用
+    subscribe_key: Option<String>,
+}
+
+impl MsSocketConnection {
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+}
+
+///
+/// websocket 连接池，每次选择进行中请求数最少的连接
+#[derive(Debug)]
+pub struct MsSocketPool {
+    list: Vec<Arc<MsSocketConnection>>,
+    max_in_flight: usize,
+    semaphore: Arc<Semaphore>,
+}
+
+///
+/// 连接池占用凭证，请求结束后释放
+#[derive(Debug)]
+pub struct MsSocketPermit {
+    pub(crate) conn: Arc<MsSocketConnection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for MsSocketPermit {
+    fn drop(&mut self) {
+        self.conn.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.conn.touch();
+    }
+}
+
+impl MsSocketPool {
+    pub fn new(size: usize, max_in_flight: usize, subscribe_key: Option<String>) -> Self {
+        let size = size.max(1);
+        let max_in_flight = max_in_flight.max(1);
+        let list = (0..size)
+            .map(|_| {
+                Arc::new(MsSocketConnection {
+                    tx: Arc::new(Mutex::new(None)),
+                    state: watch::channel(MsSocketState::Disconnected).0,
+                    failures: AtomicUsize::new(0),
+                    in_flight: AtomicUsize::new(0),
+                    generation: AtomicUsize::new(0),
+                    last_active: std::sync::Mutex::new(Instant::now()),
+                    subscribe_key: subscribe_key.clone(),
+                })
+            })
+            .collect();
+        MsSocketPool {
+            list,
+            max_in_flight,
+            semaphore: Arc::new(Semaphore::new(size * max_in_flight)),
+        }
+    }
+
+    /// 根据程序配置创建连接池
+    pub fn from_args() -> Self {
+        let args = AppArgs::parse_macro();
+        Self::new(args.ws_pool_size, args.ws_max_in_flight, None)
+    }
+
+    /// 根据程序配置创建订阅 key 的连接池
+    pub fn from_args_with_subscribe_key(hash: &str) -> Self {
+        let args = AppArgs::parse_macro();
+        Self::new(
+            args.ws_pool_size,
+            args.ws_max_in_flight,
+            Some(hash.to_owned()),
+        )
+    }
+
+    ///
+    /// 获取进行中请求数最少的连接，所有连接都达到上限时等待
+    pub async fn acquire(&self) -> MsSocketPermit {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("连接池信号量已关闭");
+        loop {
+            let conn = self
+                .list
+                .iter()
+                .min_by_key(|i| i.in_flight.load(Ordering::Acquire))
+                .unwrap();
+            let count = conn.in_flight.load(Ordering::Acquire);
+            // 持有信号量时必然存在未达到上限的连接，比较交换失败说明被其他请求抢先，重新选择
+            if count < self.max_in_flight
+                && conn
+                    .in_flight
+                    .compare_exchange(count, count + 1, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+            {
+                conn.touch();
+                return MsSocketPermit {
+                    conn: conn.clone(),
+                    _permit: permit,
+                };
+            }
+            tokio::task::yield_now().await;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -113,8 +688,248 @@ where
     T: AzureApiSpeakerList + AzureApiNewWebsocket + AzureApiGenerateXMML,
 {
     azure_api: Arc<T>,
-    tx: Arc<Mutex<Option<WebsocketRt>>>,
-    new: AtomicBool,
+    pool: MsSocketPool,
+}
+
+///
+/// 计算第 `failures` 次连接失败后的等待时间
+pub(crate) fn connect_backoff(failures: usize) -> Duration {
+    let delay = MS_SOCKET_BACKOFF_MIN * 2u32.pow(failures.saturating_sub(1).min(16) as u32);
+    delay.min(MS_SOCKET_BACKOFF_MAX)
+}
+
+/// 订阅 key 连接失败后的最短冷却时间
+const SUBSCRIBE_KEY_COOLDOWN_MIN: Duration = Duration::from_secs(10);
+/// 订阅 key 连接失败后的最长冷却时间
+const SUBSCRIBE_KEY_COOLDOWN_MAX: Duration = Duration::from_secs(10 * 60);
+
+/// 程序配置的订阅 key 健康状态，key 为 [AzureSubscribeKey::hash_str]
+static SUBSCRIBE_KEY_HEALTH_MAP: Lazy<std::sync::Mutex<HashMap<String, SubscribeKeyHealth>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+///
+/// 订阅 key 的健康状态，连接失败 (如认证失败 401、请求过多 429) 后在冷却时间内不再使用该 key
+#[derive(Debug, Default, Clone)]
+pub struct SubscribeKeyHealth {
+    /// 连续失败次数
+    pub failures: usize,
+    /// 冷却结束时间
+    pub cooldown_until: Option<Instant>,
+    /// 最后一次错误
+    pub last_error: Option<String>,
+}
+
+impl SubscribeKeyHealth {
+    ///
+    /// 是否可以使用，不在冷却时间内即可使用
+    pub fn is_available(&self, now: Instant) -> bool {
+        self.cooldown_until.map(|i| now >= i).unwrap_or(true)
+    }
+
+    ///
+    /// 记录一次失败，冷却时间随连续失败次数翻倍
+    pub(crate) fn record_failure(hash: &str, reason: &str) {
+        let mut map = SUBSCRIBE_KEY_HEALTH_MAP.lock().unwrap();
+        let health = map.entry(hash.to_owned()).or_default();
+        health.failures += 1;
+        let cooldown =
+            SUBSCRIBE_KEY_COOLDOWN_MIN * 2u32.pow(health.failures.saturating_sub(1).min(16) as u32);
+        let cooldown = cooldown.min(SUBSCRIBE_KEY_COOLDOWN_MAX);
+        health.cooldown_until = Some(Instant::now() + cooldown);
+        health.last_error = Some(reason.to_owned());
+        warn!(
+            "订阅key {} 连续 {} 次连接失败，{:?} 内不再使用: {}",
+            hash, health.failures, cooldown, reason
+        );
+    }
+
+    ///
+    /// 连接成功后清除失败记录
+    fn record_success(hash: &str) {
+        let mut map = SUBSCRIBE_KEY_HEALTH_MAP.lock().unwrap();
+        if let Some(health) = map.get_mut(hash) {
+            if health.failures > 0 {
+                debug!("订阅key {} 已恢复", hash);
+            }
+            health.failures = 0;
+            health.cooldown_until = None;
+        }
+    }
+
+    ///
+    /// 冷却中的订阅 key 及其最后一次错误
+    fn unavailable_list() -> Vec<(String, String)> {
+        let now = Instant::now();
+        SUBSCRIBE_KEY_HEALTH_MAP
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, health)| !health.is_available(now))
+            .map(|(hash, health)| (hash.clone(), health.last_error.clone().unwrap_or_default()))
+            .collect()
+    }
+}
+
+///
+/// 从下标 `use_index` 开始轮流选择不在冷却时间内、本次请求未尝试过且当月剩余额度足够发送 `chars` 个字符的订阅 key，
+/// 选中后立即预留 `chars` 个字符，请求未能发送时需要调用 [refund_subscribe_usage] 退回，
+/// key 中未指定额度时使用 `default_budget`，为 0 时不限制
+///
+/// 所有可用的 key 额度都不足时返回 [MsTtsMsgError::QuotaExceeded]，没有可用的 key 时返回 [MsTtsMsgError::Unavailable]
+pub(crate) async fn select_subscribe_key<'a>(
+    list: &'a [AzureSubscribeKey],
+    use_index: &Mutex<usize>,
+    tried_list: &[String],
+    chars: u64,
+    default_budget: u64,
+) -> Result<&'a AzureSubscribeKey, MsTtsMsgError> {
+    let mut index = use_index.lock().await;
+    let now = Instant::now();
+    let map = SUBSCRIBE_KEY_HEALTH_MAP.lock().unwrap();
+    let mut is_quota_exceeded = false;
+    for offset in 0..list.len() {
+        let i = (*index + offset) % list.len();
+        let hash = list[i].hash_str();
+        if tried_list.contains(&hash) {
+            continue;
+        }
+        if !map.get(&hash).map(|h| h.is_available(now)).unwrap_or(true) {
+            continue;
+        }
+        let budget = list[i].2.unwrap_or(default_budget);
+        if !try_reserve_subscribe_usage(&list[i].usage_id(), budget, chars) {
+            is_quota_exceeded = true;
+            continue;
+        }
+        *index = (i + 1) % list.len();
+        return Ok(&list[i]);
+    }
+    if is_quota_exceeded {
+        Err(MsTtsMsgError::QuotaExceeded)
+    } else {
+        Err(MsTtsMsgError::Unavailable)
+    }
+}
+
+///
+/// 确保连接可用，未连接时建立新连接并启动响应处理，正在连接或等待重试时等待状态变化
+///
+/// 熔断期间直接返回 [MsTtsMsgError::Unavailable]。`fail_fast` 为 true 时不等待重试，处于等待重试状态返回
+/// [MsTtsMsgError::Unavailable]，本次建立连接失败返回 [MsTtsMsgError::ConnectFailed]，由调用方切换到其他订阅 key
+pub(crate) async fn ensure_connected<T>(
+    api_name: &'static str,
+    azure_api: Arc<T>,
+    conn: &Arc<MsSocketConnection>,
+    cache_db: MsTtsCacheDb,
+    fail_fast: bool,
+) -> Result<(), MsTtsMsgError>
+where
+    T: AzureApiNewWebsocket + Send + Sync + 'static,
+{
+    loop {
+        let mut state_rx = conn.state.subscribe();
+        let state = *state_rx.borrow_and_update();
+        let now = Instant::now();
+        let attempt = match state {
+            MsSocketState::Connected => {
+                trace!("存在websocket连接，继续处理");
+                return Ok(());
+            }
+            MsSocketState::Disconnected => true,
+            MsSocketState::Backoff(until) | MsSocketState::Failed(until) if now >= until => true,
+            MsSocketState::Failed(_) => {
+                debug!("连接失败次数过多，熔断中");
+                return Err(MsTtsMsgError::Unavailable);
+            }
+            MsSocketState::Backoff(_) if fail_fast => {
+                debug!("连接失败，等待重试中");
+                return Err(MsTtsMsgError::Unavailable);
+            }
+            MsSocketState::Connecting | MsSocketState::Backoff(_) => false,
+        };
+
+        // 只有将状态切换为 Connecting 的请求负责建立连接
+        if attempt
+            && conn.state.send_if_modified(|s| {
+                if *s == state {
+                    *s = MsSocketState::Connecting;
+                    true
+                } else {
+                    false
+                }
+            })
+        {
+            debug!("websocket is not connected");
+            let result =
+                tokio::time::timeout(MS_SOCKET_CONNECT_TIMEOUT, azure_api.get_connection())
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(TTSServerError::ThirdPartyApiCallFailed(
+                            "建立 websocket 连接超时".to_owned(),
+                        ))
+                    });
+            match result {
+                Ok(ws) => {
+                    trace!("websocket连接成功");
+                    let (tx_tmp, rx_tmp) = ws.split();
+                    let generation = {
+                        let mut tx = conn.tx.lock().await;
+                        *tx = Some(tx_tmp);
+                        conn.generation.fetch_add(1, Ordering::AcqRel) + 1
+                    };
+                    conn.touch();
+                    conn.failures.store(0, Ordering::Release);
+                    let conn_tmp = Arc::clone(conn);
+                    let db_tmp = cache_db.clone();
+                    trace!("启动消息处理线程");
+                    crate::GLOBAL_EB.runtime.spawn(process_response_body(
+                        api_name, rx_tmp, conn_tmp, generation, db_tmp,
+                    ));
+                    crate::GLOBAL_EB.runtime.spawn(keepalive(
+                        api_name,
+                        azure_api.clone(),
+                        Arc::clone(conn),
+                        generation,
+                        cache_db,
+                    ));
+                    conn.state.send_replace(MsSocketState::Connected);
+                    return Ok(());
+                }
+                Err(e) => {
+                    let failures = conn.failures.fetch_add(1, Ordering::AcqRel) + 1;
+                    let next = if failures >= MS_SOCKET_MAX_FAILURES {
+                        error!(
+                            "websocket 连续 {} 次连接失败，{:?} 内不再尝试连接: {:?}",
+                            failures, MS_SOCKET_CIRCUIT_COOLDOWN, e
+                        );
+                        MsSocketState::Failed(Instant::now() + MS_SOCKET_CIRCUIT_COOLDOWN)
+                    } else {
+                        let delay = connect_backoff(failures);
+                        warn!("websocket 连接失败，{:?} 后重试: {:?}", delay, e);
+                        MsSocketState::Backoff(Instant::now() + delay)
+                    };
+                    conn.state.send_replace(next);
+                    if fail_fast {
+                        return Err(MsTtsMsgError::ConnectFailed(format!("{:?}", e)));
+                    }
+                }
+            }
+            continue;
+        }
+
+        // 等待其他请求建立连接或等待重试时间到达
+        match state {
+            MsSocketState::Backoff(until) => {
+                tokio::select! {
+                    _ = state_rx.changed() => {}
+                    _ = tokio::time::sleep_until(until) => {}
+                }
+            }
+            _ => {
+                let _ = state_rx.changed().await;
+            }
+        }
+    }
 }
 
 ///
@@ -134,26 +949,21 @@ pub(crate) async fn register_service() {
 
     // 注册 edge 免费接口的服务
     if !args.close_edge_free_api {
-        /// edge 免费接口 socket 连接
-        static SOCKET_TX_EDGE_FREE: OnceCell<Arc<Mutex<Option<WebsocketRt>>>> =
-            OnceCell::const_new();
+        /// edge 免费接口 socket 连接池
+        static SOCKET_POOL_EDGE_FREE: Lazy<MsSocketPool> = Lazy::new(MsSocketPool::from_args);
 
         /// edge 免费接口 数据缓存
-        static MS_TTS_DATA_CACHE_EDGE_FREE: Lazy<
-            Arc<Mutex<HashMap<String, Arc<Mutex<MsTtsCache>>>>>,
-        > = Lazy::new(|| {
+        static MS_TTS_DATA_CACHE_EDGE_FREE: Lazy<MsTtsCacheDb> = Lazy::new(|| {
             let kk = HashMap::new();
             Arc::new(Mutex::new(kk))
         });
-        /// edge 免费接口 新请求限制措施
-        static MS_TTS_GET_NEW_EDGE_FREE: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
+        spawn_cache_sweeper(MS_TTS_DATA_CACHE_EDGE_FREE.clone());
 
         AzureApiEdgeFree::new().get_vices_list().await.unwrap();
 
         crate::GLOBAL_EB
             .consumer("tts_ms_edge_free", |fn_msg| async move {
                 let eb_msg = fn_msg.msg.clone();
-                let eb = Arc::clone(&fn_msg.eb);
                 let ll = Bytes::from(
                     eb_msg
                         .body()
@@ -163,82 +973,65 @@ pub(crate) async fn register_service() {
                         .to_vec(),
                 );
                 let request = MsTtsMsgRequest::from_bytes(ll);
-                let tx_socket = Arc::clone(
-                    SOCKET_TX_EDGE_FREE
-                        .get_or_init(|| async { Arc::new(Mutex::new(None)) })
-                        .await,
-                );
-
-                if !MS_TTS_GET_NEW_EDGE_FREE.load(Ordering::Relaxed)
-                    && !tx_socket.clone().lock().await.is_some()
-                {
-                    MS_TTS_GET_NEW_EDGE_FREE.store(true, Ordering::Release);
-                    debug!("websocket is not connected");
-                    let mut result = AzureApiEdgeFree::new().get_connection().await;
-                    'outer: loop {
-                        // 'outer:
-                        trace!("进入循环，防止websocket连接失败");
-                        let result_bool = result.is_ok();
-
-                        if result_bool {
-                            trace!("websocket连接成功");
-                            let (tx_tmp, rx_tmp) = result.unwrap().split();
-                            *tx_socket.clone().lock().await = Some(tx_tmp);
-                            let tx_tmp1 = Arc::clone(&tx_socket);
-                            trace!("启动消息处理线程");
-                            eb.runtime.spawn(async move {
-                                process_response_body(
-                                    rx_tmp,
-                                    tx_tmp1,
-                                    MS_TTS_DATA_CACHE_EDGE_FREE.clone(),
-                                )
-                                .await;
-                            });
-                            trace!("准备跳出循环");
-                            break 'outer;
-                        } else {
-                            trace!("reconnection websocket");
-                            sleep(Duration::from_secs(1)).await;
-                            result = AzureApiEdgeFree::new().get_connection().await;
-                        }
-                    }
-                    trace!("循环已跳出");
-                    MS_TTS_GET_NEW_EDGE_FREE.store(false, Ordering::Release)
-                } else {
-                    while MS_TTS_GET_NEW_EDGE_FREE.load(Ordering::Relaxed)
-                        || !tx_socket.clone().lock().await.is_some()
-                    {
-                        tokio::time::sleep(Duration::from_millis(200)).await;
-                    }
-                }
-                trace!("存在websocket连接，继续处理");
+                let permit = SOCKET_POOL_EDGE_FREE.acquire().await;
+                let connected = ensure_connected(
+                    "tts_ms_edge_free",
+                    AzureApiEdgeFree::new(),
+                    &permit.conn,
+                    MS_TTS_DATA_CACHE_EDGE_FREE.clone(),
+                    false,
+                )
+                .await;
                 let request_id = request.request_id.clone();
+                if let Err(e) = connected {
+                    let body = MsTtsMsgResponse::failure(request_id, e);
+                    eb_msg.reply(body.to_vec().into()).await;
+                    return;
+                }
+                let tx_socket = permit.conn.tx.clone();
+
                 debug!("发送请求: {} | {:?}", request.request_id, request);
 
+                let request_timeout = Duration::from_secs(AppArgs::parse_macro().request_timeout);
+                let stream = if request.stream {
+                    MS_TTS_STREAM_SENDER.lock().await.remove(&request_id)
+                } else {
+                    None
+                };
+
+                let retry_request = request.clone();
                 let xmml = AzureApiEdgeFree::new()
                     .generate_xmml(request)
                     .await
                     .expect("generate_xmml 错误");
 
-                // 向 websocket 发送消息
+                // 持有连接锁时登记请求，保证连接断开时能找到该连接上的所有请求
+                let mut gg = tx_socket.lock().await;
                 MS_TTS_DATA_CACHE_EDGE_FREE.clone().lock().await.insert(
-                    request_id,
+                    request_id.clone(),
                     Arc::new(Mutex::new(MsTtsCache {
                         data: BytesMut::new(),
                         reply: eb_msg.clone(),
                         file_type: None,
+                        boundaries: Vec::new(),
+                        stream,
+                        deadline: Instant::now() + request_timeout,
+                        request: retry_request,
+                        generation: permit.conn.generation.load(Ordering::Acquire),
+                        permit,
                     })),
                 );
 
-                let mut gg = tx_socket.lock().await;
-                let socket = gg.as_mut();
-                if let Some(s) = socket {
-                    for i in xmml {
-                        debug!("\n >>>>>>>>>>  xmml data\n{}\n <<<<<<<<<<\n", &i);
-                        s.send(Message::Text(i)).await.unwrap();
-                    }
-                    drop(gg)
-                }
+                // 向 websocket 发送消息
+                send_xmml(
+                    "tts_ms_edge_free",
+                    gg.as_mut(),
+                    xmml,
+                    &request_id,
+                    MS_TTS_DATA_CACHE_EDGE_FREE.clone(),
+                )
+                .await;
+                drop(gg)
             })
             .await;
     }
@@ -273,12 +1066,12 @@ pub(crate) async fn register_service() {
             .get_or_init(|| async move {
                 let mut h = HashMap::new();
                 for subscribe_key in OFFICIAL_SUBSCRIBE_API_LIST.get().unwrap().iter() {
+                    let hash = subscribe_key.hash_str();
                     let info = MsSocketInfo {
                         azure_api: AzureApiSubscribeToken::new_from_subscribe_key(subscribe_key),
-                        tx: Arc::new(Mutex::new(None)),
-                        new: AtomicBool::new(false),
+                        pool: MsSocketPool::from_args_with_subscribe_key(&hash),
                     };
-                    h.insert(subscribe_key.hash_str(), Arc::new(info));
+                    h.insert(hash, Arc::new(info));
                 }
                 Mutex::new(h)
             })
@@ -291,12 +1084,11 @@ pub(crate) async fn register_service() {
         }
 
         /// 官网 订阅API 响应数据缓存
-        static MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE: Lazy<
-            Arc<Mutex<HashMap<String, Arc<Mutex<MsTtsCache>>>>>,
-        > = Lazy::new(|| {
+        static MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE: Lazy<MsTtsCacheDb> = Lazy::new(|| {
             let kk = HashMap::new();
             Arc::new(Mutex::new(kk))
         });
+        spawn_cache_sweeper(MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE.clone());
 
         #[macro_export]
         macro_rules! get_subscribe_api_tx_for_map {
@@ -309,7 +1101,6 @@ pub(crate) async fn register_service() {
         crate::GLOBAL_EB
             .consumer("tts_ms_subscribe_api", |fn_msg| async move {
                 let eb_msg = fn_msg.msg.clone();
-                let eb = Arc::clone(&fn_msg.eb);
                 let ll = Bytes::from(
                     eb_msg
                         .body()
@@ -320,147 +1111,284 @@ pub(crate) async fn register_service() {
                 );
                 let request = MsTtsMsgRequest::from_bytes(ll);
 
-                let key_info = if request.region.is_some() && request.subscribe_key.is_some()
-                {
-                    let key_tmp = String::from(request.subscribe_key.as_ref().unwrap());
-                    let region_tmp = String::from(request.region.as_ref().unwrap());
+                let request_id = request.request_id.clone();
+                // 请求中指定了订阅 key 时只使用该 key，否则按顺序选择健康的 key，连接失败时切换到下一个
+                let is_custom_key = request.region.is_some() && request.subscribe_key.is_some();
+                let reserve_chars = request.reserve_chars();
+                let default_budget = AppArgs::parse_macro().subscribe_key_monthly_chars;
+                let mut tried_list: Vec<String> = Vec::new();
+                let (key_info, permit) = loop {
+                    // 程序配置的订阅 key 选中时已预留字符用量，usage_id 用于连接失败时退回
+                    let (hash, usage_id, key_info) = if is_custom_key {
+                        let key_tmp = String::from(request.subscribe_key.as_ref().unwrap());
+                        let region_tmp = String::from(request.region.as_ref().unwrap());
 
-                    let api_key = AzureSubscribeKey(
-                        key_tmp,
-                        AzureApiRegionIdentifier::from(&region_tmp).unwrap(),
-                    );
-                    let hash = api_key.hash_str();
-                    let if_contains = get_subscribe_api_tx_for_map!().contains_key(&hash);
-                    let key_info = if if_contains {
-                        get_subscribe_api_tx_for_map!().get(&hash).unwrap().clone()
+                        let api_key = AzureSubscribeKey(
+                            key_tmp,
+                            AzureApiRegionIdentifier::from(&region_tmp).unwrap(),
+                            None,
+                        );
+                        let hash = api_key.hash_str();
+                        let if_contains = get_subscribe_api_tx_for_map!().contains_key(&hash);
+                        let key_info = if if_contains {
+                            get_subscribe_api_tx_for_map!().get(&hash).unwrap().clone()
+                        } else {
+                            let key_info = Arc::new(MsSocketInfo {
+                                azure_api: AzureApiSubscribeToken::new_from_subscribe_key(&api_key),
+                                pool: MsSocketPool::from_args(),
+                            });
+                            get_subscribe_api_tx_for_map!().insert(hash.clone(), key_info.clone());
+                            key_info
+                        };
+                        (hash, None, key_info)
                     } else {
-                        let key_info = Arc::new(MsSocketInfo {
-                            azure_api: AzureApiSubscribeToken::new_from_subscribe_key(&api_key),
-                            tx: Arc::new(Mutex::new(None)),
-                            new: AtomicBool::new(false),
-                        });
-                        get_subscribe_api_tx_for_map!().insert(hash, key_info.clone());
-                        key_info
+                        let selected = select_subscribe_key(
+                            OFFICIAL_SUBSCRIBE_API_LIST.get().unwrap(),
+                            &OFFICIAL_SUBSCRIBE_API_USE_INDEX,
+                            &tried_list,
+                            reserve_chars,
+                            default_budget,
+                        )
+                        .await;
+                        let subscribe_key = match selected {
+                            Ok(subscribe_key) => subscribe_key,
+                            Err(e) => {
+                                warn!(
+                                    "没有可用的订阅key，已尝试 {} 个: {:?}，不可用的订阅key: {:?}",
+                                    tried_list.len(),
+                                    e,
+                                    SubscribeKeyHealth::unavailable_list()
+                                );
+                                let body = MsTtsMsgResponse::failure(request_id, e);
+                                eb_msg.reply(body.to_vec().into()).await;
+                                return;
+                            }
+                        };
+                        let hash = subscribe_key.hash_str();
+                        let key_info = get_subscribe_api_tx_for_map!().get(&hash).unwrap().clone();
+                        (hash, Some(subscribe_key.usage_id()), key_info)
                     };
-                    key_info
-                } else {
-                    let index = *OFFICIAL_SUBSCRIBE_API_USE_INDEX.lock().await;
-                    let key_info = OFFICIAL_SUBSCRIBE_API_LIST
-                        .get()
-                        .unwrap()
-                        .get(index)
-                        .unwrap();
-                    get_subscribe_api_tx_for_map!()
-                        .get(&key_info.hash_str())
-                        .unwrap()
-                        .clone()
-                };
-                let azure_api = key_info.azure_api.clone();
 
-                let tx_socket = key_info.tx.clone();
-
-                if !key_info.new.load(Ordering::Relaxed)
-                    && !tx_socket.clone().lock().await.is_some()
-                {
-                    key_info.new.store(true, Ordering::Release);
-
-                    debug!("websocket is not connected");
-                    // let mut info_mut = ;
-                    // let token_info = key_info.lock().await.azure_api.clone();
-                    let mut result = azure_api.get_connection().await;
-                    // drop(info_mut);
-                    // let mut result = new_websocket_edge_free().await;
-                    'outer: loop {
-                        // 'outer:
-                        trace!("进入循环，防止websocket连接失败");
-                        let result_bool = result.is_ok();
-
-                        if result_bool {
-                            trace!("websocket连接成功");
-                            let (tx_tmp, rx_tmp) = result.unwrap().split();
-                            *tx_socket.clone().lock().await = Some(tx_tmp);
-                            let tx_tmp1 = Arc::clone(&tx_socket);
-                            trace!("启动消息处理线程");
-                            eb.runtime.spawn(async move {
-                                process_response_body(
-                                    rx_tmp,
-                                    tx_tmp1,
-                                    MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE.clone(),
-                                )
-                                .await;
-                                // 更新下一次进行连接的 Api 下标
-                                let num = *OFFICIAL_SUBSCRIBE_API_USE_INDEX.lock().await + 1;
-                                if num > OFFICIAL_SUBSCRIBE_API_LIST.get().unwrap().len() {
-                                    *OFFICIAL_SUBSCRIBE_API_USE_INDEX.lock().await = 0;
-                                } else {
-                                    *OFFICIAL_SUBSCRIBE_API_USE_INDEX.lock().await = num;
-                                }
-                            });
-                            trace!("准备跳出循环");
-                            break 'outer;
-                        } else {
-                            trace!("reconnection websocket");
-                            sleep(Duration::from_secs(1)).await;
-                            result = azure_api.get_connection().await;
+                    let permit = key_info.pool.acquire().await;
+                    let connected = ensure_connected(
+                        "tts_ms_subscribe_api",
+                        key_info.azure_api.clone(),
+                        &permit.conn,
+                        MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE.clone(),
+                        !is_custom_key,
+                    )
+                    .await;
+                    match connected {
+                        Ok(_) => {
+                            if !is_custom_key {
+                                SubscribeKeyHealth::record_success(&hash);
+                            }
+                            break (key_info, permit);
+                        }
+                        Err(e) if !is_custom_key => {
+                            if let Some(usage_id) = usage_id.as_ref() {
+                                refund_subscribe_usage(usage_id, reserve_chars);
+                            }
+                            // 只有本次建立连接失败才计入失败次数，等待重试或熔断中的连接直接切换
+                            if let MsTtsMsgError::ConnectFailed(reason) = &e {
+                                SubscribeKeyHealth::record_failure(&hash, reason);
+                            }
+                            tried_list.push(hash);
+                        }
+                        Err(e) => {
+                            let body = MsTtsMsgResponse::failure(request_id, e);
+                            eb_msg.reply(body.to_vec().into()).await;
+                            return;
                         }
                     }
-                    trace!("循环已跳出");
-                    key_info.new.store(false, Ordering::Release)
-                } else {
-                    while key_info.new.load(Ordering::Relaxed)
-                        || !tx_socket.clone().lock().await.is_some()
-                    {
-                        tokio::time::sleep(Duration::from_millis(200)).await;
-                    }
-                }
-                trace!("存在websocket连接，继续处理");
+                };
+                let azure_api = key_info.azure_api.clone();
+                let tx_socket = permit.conn.tx.clone();
 
-                let request_id = request.request_id.clone();
                 debug!("发送请求: {} | {:?}", request_id, request);
 
+                let request_timeout = Duration::from_secs(AppArgs::parse_macro().request_timeout);
+                let stream = if request.stream {
+                    MS_TTS_STREAM_SENDER.lock().await.remove(&request_id)
+                } else {
+                    None
+                };
+
+                let retry_request = request.clone();
                 let xmml = azure_api
                     .generate_xmml(request)
                     .await
                     .expect("generate_xmml 错误");
 
-                // 向 websocket 发送消息
+                // 持有连接锁时登记请求，保证连接断开时能找到该连接上的所有请求
+                let mut gg = tx_socket.lock().await;
                 MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE
                     .clone()
                     .lock()
                     .await
                     .insert(
-                        request_id,
+                        request_id.clone(),
                         Arc::new(Mutex::new(MsTtsCache {
                             data: BytesMut::new(),
                             reply: eb_msg.clone(),
                             file_type: None,
+                            boundaries: Vec::new(),
+                            stream,
+                            deadline: Instant::now() + request_timeout,
+                            request: retry_request,
+                            generation: permit.conn.generation.load(Ordering::Acquire),
+                            permit,
                         })),
                     );
 
-                let mut gg = tx_socket.lock().await;
-                let socket = gg.as_mut();
-                if let Some(s) = socket {
-                    for i in xmml {
-                        debug!("\n >>>>>>>>>>  xmml data\n{}\n <<<<<<<<<<\n", &i);
-                        s.send(Message::Text(i)).await.unwrap();
-                    }
-                    drop(gg)
-                }
+                // 向 websocket 发送消息
+                send_xmml(
+                    "tts_ms_subscribe_api",
+                    gg.as_mut(),
+                    xmml,
+                    &request_id,
+                    MS_TTS_DATA_CACHE_OFFICIAL_SUBSCRIBE.clone(),
+                )
+                .await;
+                drop(gg)
             })
             .await;
     }
 }
 
+///
+/// 连接保活，定时发送 ping，连接空闲超过设定时长后在后台建立新连接替换旧连接
+///
+/// 连接断开后退出，下一次请求重新建立连接时会启动新的保活任务
+async fn keepalive<T>(
+    api_name: &'static str,
+    azure_api: Arc<T>,
+    conn: Arc<MsSocketConnection>,
+    generation: usize,
+    cache_db: MsTtsCacheDb,
+) where
+    T: AzureApiNewWebsocket + Send + Sync + 'static,
+{
+    let args = AppArgs::parse_macro();
+    if args.ws_ping_interval == 0 {
+        return;
+    }
+    let ping_interval = Duration::from_secs(args.ws_ping_interval);
+    let idle_lifetime = Duration::from_secs(args.ws_idle_lifetime);
+    let mut generation = generation;
+    loop {
+        sleep(ping_interval).await;
+        if conn.generation.load(Ordering::Acquire) != generation {
+            break;
+        }
+
+        let idle = conn.last_active.lock().unwrap().elapsed();
+        if args.ws_idle_lifetime > 0
+            && idle >= idle_lifetime
+            && conn.in_flight.load(Ordering::Acquire) == 0
+        {
+            debug!("websocket 连接空闲 {:?}，后台重新建立连接", idle);
+            let (tx_new, rx_new) = match azure_api.get_connection().await {
+                Ok(ws) => ws.split(),
+                Err(e) => {
+                    warn!("后台重新建立 websocket 连接失败: {:?}", e);
+                    continue;
+                }
+            };
+            let mut tx = conn.tx.lock().await;
+            if conn.generation.load(Ordering::Acquire) != generation || tx.is_none() {
+                break;
+            }
+            let old = tx.replace(tx_new);
+            generation = conn.generation.fetch_add(1, Ordering::AcqRel) + 1;
+            drop(tx);
+            conn.touch();
+            crate::GLOBAL_EB.runtime.spawn(process_response_body(
+                api_name,
+                rx_new,
+                conn.clone(),
+                generation,
+                cache_db.clone(),
+            ));
+            if let Some(mut old) = old {
+                if let Err(e) = old.close().await {
+                    debug!("关闭旧 websocket 连接失败: {:?}", e);
+                }
+            }
+            continue;
+        }
+
+        let mut tx = conn.tx.lock().await;
+        if conn.generation.load(Ordering::Acquire) != generation {
+            break;
+        }
+        match tx.as_mut() {
+            Some(s) => {
+                trace!("发送ping消息");
+                if let Err(e) = s.send(Message::Ping(Vec::new())).await {
+                    debug!("发送ping消息失败: {:?}", e);
+                }
+            }
+            None => break,
+        }
+    }
+    trace!("保活任务退出");
+}
+
+///
+/// 向 websocket 发送请求，连接不可用或发送失败时按连接断开处理该请求
+async fn send_xmml(
+    api_name: &'static str,
+    socket: Option<&mut WebsocketRt>,
+    xmml: Vec<String>,
+    request_id: &str,
+    cache_db: MsTtsCacheDb,
+) {
+    let result = match socket {
+        Some(s) => {
+            let mut result = Ok(());
+            for i in xmml {
+                debug!("\n >>>>>>>>>>  xmml data\n{}\n <<<<<<<<<<\n", &i);
+                result = s.send(Message::Text(i)).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+        None => Err(tokio_tungstenite::tungstenite::Error::AlreadyClosed),
+    };
+    if let Err(e) = result {
+        warn!("发送请求失败: {} | {:?}", request_id, e);
+        let cache = { cache_db.lock().await.remove(request_id) };
+        if let Some(cache) = cache {
+            retry_disconnected_requests(api_name, vec![(request_id.to_owned(), cache)]).await;
+        }
+    }
+}
+
 /// 处理微软api 响应
+///
+/// 连接结束后返回该连接是否仍是当前连接，被保活任务替换的旧连接返回 false
 #[allow(dead_code)]
 async fn process_response_body(
+    api_name: &'static str,
     rx_r: SplitStream<WebSocketStream<TlsStream<TcpStream>>>,
-    tx_r: Arc<Mutex<Option<WebsocketRt>>>,
-    cache_db: Arc<Mutex<HashMap<String, Arc<Mutex<MsTtsCache>>>>>,
-) {
+    conn: Arc<MsSocketConnection>,
+    generation: usize,
+    cache_db: MsTtsCacheDb,
+) -> bool {
     let mut rx_r = rx_r;
+    // 连接异常断开的原因，正常关闭时为 None
+    let mut close_error: Option<String> = None;
     loop {
-        let msg = rx_r.next().await.unwrap();
+        let msg = match rx_r.next().await {
+            Some(msg) => msg,
+            None => {
+                debug!("websocket 连接已关闭");
+                break;
+            }
+        };
         match msg {
             Ok(m) => {
                 trace!("收到消息");
@@ -473,12 +1401,29 @@ async fn process_response_body(
                     }
                     Message::Close(s) => {
                         debug!("被动断开连接: {:?}", s);
+                        if let Some(frame) = s {
+                            if !matches!(frame.code, CloseCode::Normal | CloseCode::Away) {
+                                close_error = Some(format!(
+                                    "连接被关闭 {} {}",
+                                    u16::from(frame.code),
+                                    frame.reason
+                                ));
+                            }
+                        }
                         break;
                     }
                     Message::Text(s) => {
                         let id = s[12..44].to_string();
                         // info!("到消息: {}", id);
                         if let Some(_i) = s.find("Path:turn.start") {
+                        } else if let Some(_i) = s.find("Path:audio.metadata") {
+                            let boundaries = MsTtsBoundary::from_metadata_message(&s);
+                            if !boundaries.is_empty() {
+                                let cache = { cache_db.lock().await.get(&id).cloned() };
+                                if let Some(cache) = cache {
+                                    cache.lock().await.boundaries.extend(boundaries);
+                                }
+                            }
                         } else if let Some(_i) = s.find("Path:turn.end") {
                             trace!("响应 {}， 结束", id);
                             let data = { cache_db.lock().await.remove(&id) };
@@ -489,7 +1434,9 @@ async fn process_response_body(
                                 let body = MsTtsMsgResponse {
                                     request_id: id,
                                     data: data.data.to_vec().clone(),
-                                    file_type: data.file_type.as_ref().unwrap().to_string(),
+                                    file_type: data.file_type.clone().unwrap_or_default(),
+                                    boundaries: data.boundaries.clone(),
+                                    error: None,
                                 };
                                 data.reply.reply(body.to_vec().into()).await;
                                 // eb_msg.reply(data.to_vec().into()).await;
@@ -511,9 +1458,22 @@ async fn process_response_body(
                             let mut body = BytesMut::from(s.as_slice());
                             let index = binary_search(&s, &TAG_BODY_SPLIT).unwrap();
                             let head = body.split_to(index + TAG_BODY_SPLIT.len());
-                            let cache = { cache_db.lock().await.get(&id).unwrap().clone() };
+                            let cache = { cache_db.lock().await.get(&id).cloned() };
+                            let cache = match cache {
+                                Some(cache) => cache,
+                                None => {
+                                    trace!("响应 不存在或已超时 ,{}", id);
+                                    continue;
+                                }
+                            };
                             let mut cache_map = cache.lock().await;
-                            cache_map.data.put(body);
+                            if let Some(stream) = cache_map.stream.as_ref() {
+                                if stream.unbounded_send(body.freeze()).is_err() {
+                                    trace!("流式响应接收端已关闭 ,{}", id);
+                                }
+                            } else {
+                                cache_map.data.put(body);
+                            }
                             if cache_map.file_type.is_none() {
                                 let head = String::from_utf8(head.to_vec()[2..head.len()].to_vec())
                                     .unwrap();
@@ -538,12 +1498,43 @@ async fn process_response_body(
             Err(e) => {
                 // trace!("收到错误消息:{:?}", e);
                 debug!("收到错误消息，被动断开连接: {:?}", e);
+                close_error = Some(format!("{:?}", e));
                 // websocket 错误的话就会断开连接
                 break;
             }
         }
     }
-    *tx_r.lock().await = None;
+    // 持有连接锁时清理，避免新连接上的请求被误清理
+    let mut tx = conn.tx.lock().await;
+    let current = conn.generation.load(Ordering::Acquire) == generation;
+    if current {
+        *tx = None;
+        conn.state.send_replace(MsSocketState::Disconnected);
+    }
+    let list = {
+        let mut db = cache_db.lock().await;
+        let mut id_list = Vec::new();
+        for (id, cache) in db.iter() {
+            let cache = cache.lock().await;
+            if Arc::ptr_eq(&cache.permit.conn, &conn) && cache.generation == generation {
+                id_list.push(id.clone());
+            }
+        }
+        id_list
+            .into_iter()
+            .filter_map(|id| db.remove(&id).map(|cache| (id, cache)))
+            .collect::<Vec<_>>()
+    };
+    drop(tx);
+    if !list.is_empty() {
+        warn!("websocket 连接断开，{} 个未完成的请求需要处理", list.len());
+        // 订阅 key 在连接建立后被拒绝 (如认证失败 401、请求过多 429) 时，先标记为不可用，重试的请求会切换到其他 key
+        if let (Some(hash), Some(reason)) = (conn.subscribe_key.as_ref(), close_error.as_ref()) {
+            SubscribeKeyHealth::record_failure(hash, reason);
+        }
+    }
+    retry_disconnected_requests(api_name, list).await;
+    current
 }
 
 #[derive(Debug)]
@@ -1,2 +1,4 @@
 pub(crate) mod azure_api_test;
+pub(crate) mod ms_tts_test;
 pub(crate) mod other;
+pub(crate) mod utils_test;
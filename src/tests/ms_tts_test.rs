@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix_web::http::StatusCode;
+use event_bus::async_utils::BoxFutureSync;
+use tokio::{net::TcpStream, sync::Mutex, time::Instant};
+use tokio_native_tls::TlsStream;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::{
+    error::TTSServerError,
+    ms_tts::{
+        connect_backoff, ensure_connected, take_expired, MsSocketPool, MsTtsMsgError,
+        MsTtsMsgResponse, MS_SOCKET_BACKOFF_MAX, MS_SOCKET_BACKOFF_MIN, MS_SOCKET_CIRCUIT_COOLDOWN,
+        MS_SOCKET_MAX_FAILURES, MS_TTS_DIALOGUE_MAX_VOICES,
+    },
+    utils::azure_api::{
+        collating_list_of_pronouncers_arc, AzureApiNewWebsocket, MsApiOrigin, MsTtsMsgRequest,
+        VoicesItem, AZURE_SPEAKERS_LIST_FILE,
+    },
+    web::controller::{check_ms_tts_response, MsTtsMsgRequestJson},
+};
+
+/// 请求超时清理测试
+#[tokio::test]
+async fn test_take_expired() {
+    let now = Instant::now();
+    let cache_db: Mutex<HashMap<String, Arc<Mutex<Instant>>>> = Mutex::new(HashMap::from([
+        ("expired".to_owned(), Arc::new(Mutex::new(now))),
+        (
+            "pending".to_owned(),
+            Arc::new(Mutex::new(now + Duration::from_secs(60))),
+        ),
+    ]));
+
+    let expired = take_expired(&cache_db, now, |i| *i).await;
+    let id_list: Vec<&str> = expired.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(id_list, vec!["expired"]);
+    // 未超时的请求保留在缓存中
+    assert!(cache_db.lock().await.contains_key("pending"));
+    assert!(!cache_db.lock().await.contains_key("expired"));
+    assert!(take_expired(&cache_db, now, |i| *i).await.is_empty());
+
+    // 超时请求回复的错误转换为 504
+    let body = MsTtsMsgResponse::failure("expired".to_owned(), MsTtsMsgError::Timeout);
+    let err = check_ms_tts_response("expired", Some(Arc::new(body.to_vec().into()))).unwrap_err();
+    assert_eq!(err.http_status, StatusCode::GATEWAY_TIMEOUT);
+}
+
+/// 连接池选择测试
+#[tokio::test]
+async fn test_socket_pool_acquire() {
+    let pool = MsSocketPool::new(2, 3, None);
+
+    // 每次选择进行中请求数最少的连接
+    let p1 = pool.acquire().await;
+    let p2 = pool.acquire().await;
+    assert!(!Arc::ptr_eq(&p1.conn, &p2.conn));
+    let p3 = pool.acquire().await;
+    let p4 = pool.acquire().await;
+    assert!(!Arc::ptr_eq(&p3.conn, &p4.conn));
+
+    // 释放后选择进行中请求数变少的连接
+    let conn = p1.conn.clone();
+    drop(p1);
+    let p5 = pool.acquire().await;
+    assert!(Arc::ptr_eq(&p5.conn, &conn));
+
+    // 所有连接都达到上限时等待，有请求结束后继续
+    let p6 = pool.acquire().await;
+    let p7 = pool.acquire().await;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), pool.acquire())
+            .await
+            .is_err()
+    );
+    let conn = p2.conn.clone();
+    drop(p2);
+    let p8 = tokio::time::timeout(Duration::from_millis(50), pool.acquire())
+        .await
+        .unwrap();
+    assert!(Arc::ptr_eq(&p8.conn, &conn));
+    drop((p3, p4, p5, p6, p7, p8));
+}
+
+/// 连接失败等待时间测试
+#[test]
+fn test_connect_backoff() {
+    assert_eq!(connect_backoff(1), MS_SOCKET_BACKOFF_MIN);
+    assert_eq!(connect_backoff(2), MS_SOCKET_BACKOFF_MIN * 2);
+    assert_eq!(connect_backoff(3), MS_SOCKET_BACKOFF_MIN * 4);
+    // 等待时间随失败次数增长，不超过上限
+    for failures in 1..64 {
+        assert!(connect_backoff(failures) <= connect_backoff(failures + 1));
+        assert!(connect_backoff(failures) <= MS_SOCKET_BACKOFF_MAX);
+    }
+    assert_eq!(connect_backoff(usize::MAX), MS_SOCKET_BACKOFF_MAX);
+}
+
+/// 总是连接失败的接口，记录尝试连接的次数
+#[derive(Default)]
+struct FailedWebsocketApi(AtomicUsize);
+
+impl AzureApiNewWebsocket for FailedWebsocketApi {
+    fn get_connection(
+        &self,
+    ) -> BoxFutureSync<'_, Result<WebSocketStream<TlsStream<TcpStream>>, TTSServerError>> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async {
+            Err(TTSServerError::ThirdPartyApiCallFailed(
+                "连接失败".to_owned(),
+            ))
+        })
+    }
+}
+
+/// 连接熔断测试
+#[tokio::test(start_paused = true)]
+async fn test_connect_circuit_breaker() {
+    let api = Arc::new(FailedWebsocketApi::default());
+    let pool = MsSocketPool::new(1, 1, None);
+    let permit = pool.acquire().await;
+    let cache_db = Arc::new(Mutex::new(HashMap::new()));
+    let connect = || ensure_connected("test", api.clone(), &permit.conn, cache_db.clone(), true);
+    let attempts = || api.0.load(Ordering::SeqCst);
+
+    for failures in 1..MS_SOCKET_MAX_FAILURES {
+        assert!(matches!(
+            connect().await,
+            Err(MsTtsMsgError::ConnectFailed(_))
+        ));
+        assert_eq!(attempts(), failures);
+        // 等待重试期间直接失败，不再尝试连接
+        assert!(matches!(connect().await, Err(MsTtsMsgError::Unavailable)));
+        assert_eq!(attempts(), failures);
+        tokio::time::advance(connect_backoff(failures)).await;
+    }
+
+    // 连续失败达到上限后熔断
+    assert!(matches!(
+        connect().await,
+        Err(MsTtsMsgError::ConnectFailed(_))
+    ));
+    assert_eq!(attempts(), MS_SOCKET_MAX_FAILURES);
+    tokio::time::advance(MS_SOCKET_CIRCUIT_COOLDOWN - Duration::from_millis(1)).await;
+    assert!(matches!(connect().await, Err(MsTtsMsgError::Unavailable)));
+    assert_eq!(attempts(), MS_SOCKET_MAX_FAILURES);
+
+    // 熔断结束后允许再次尝试连接
+    tokio::time::advance(Duration::from_millis(1)).await;
+    assert!(matches!(
+        connect().await,
+        Err(MsTtsMsgError::ConnectFailed(_))
+    ));
+    assert_eq!(attempts(), MS_SOCKET_MAX_FAILURES + 1);
+}
+
+/// 使用订阅接口的发音人列表生成合成请求
+fn build_request(informant: &str, text: &str) -> MsTtsMsgRequest {
+    let list: Vec<VoicesItem> = serde_json::from_slice(AZURE_SPEAKERS_LIST_FILE).unwrap();
+    let list: Vec<_> = list.into_iter().map(Arc::new).collect();
+    let voices_list = collating_list_of_pronouncers_arc(&list);
+    let json: MsTtsMsgRequestJson = serde_json::from_value(serde_json::json!({
+        "text": text,
+        "informant": informant,
+        "strict": true,
+    }))
+    .unwrap();
+    json.build_ms_request_with_voices(MsApiOrigin::Subscription, &voices_list, "id".to_owned())
+        .unwrap()
+}
+
+/// 多人对话合并测试
+#[test]
+fn test_merge_dialogue() {
+    let base_list = [
+        build_request("zh-CN-XiaoxiaoNeural", "测试"),
+        build_request("zh-CN-YunxiNeural", "测试"),
+    ];
+    let segment = |index: usize, text: String| MsTtsMsgRequest {
+        text,
+        ..base_list[index % 2].clone()
+    };
+    let dialogue = |count: usize| -> Vec<MsTtsMsgRequest> {
+        (0..count)
+            .map(|i| segment(i, format!("片段{:03}。", i)))
+            .collect()
+    };
+    // 合并结果中的片段按原顺序出现，且每个文档的 voice 元素数量不超过上限
+    let check_order = |result: &[MsTtsMsgRequest], count: usize| {
+        let ssml: String = result.iter().map(|i| i.ssml.clone().unwrap()).collect();
+        let mut last = 0;
+        for i in 0..count {
+            let index = ssml.find(&format!("片段{:03}。", i)).unwrap();
+            assert!(index >= last);
+            last = index;
+        }
+        for i in result {
+            let voices = i.ssml.as_ref().unwrap().matches("<voice ").count();
+            assert!(voices <= MS_TTS_DIALOGUE_MAX_VOICES);
+        }
+    };
+
+    // 超出 voice 元素数量上限
+    let count = MS_TTS_DIALOGUE_MAX_VOICES + 10;
+    let result = MsTtsMsgRequest::merge_dialogue(dialogue(count), 10000);
+    assert_eq!(result.len(), 2);
+    assert_eq!(
+        result[0].ssml.as_ref().unwrap().matches("<voice ").count(),
+        MS_TTS_DIALOGUE_MAX_VOICES
+    );
+    assert_eq!(
+        result[1].ssml.as_ref().unwrap().matches("<voice ").count(),
+        10
+    );
+    assert_eq!(result[0].informant, "zh-CN-XiaoxiaoNeural");
+    check_order(&result, count);
+
+    // 超出文本长度，每个片段 6 个字符
+    let result = MsTtsMsgRequest::merge_dialogue(dialogue(count), 20);
+    assert_eq!(result.len(), count.div_ceil(3));
+    assert!(result.iter().all(|i| i.text.is_empty()));
+    check_order(&result, count);
+
+    // 先超出 voice 元素数量上限，再超出文本长度
+    let long = |i: usize| segment(i, format!("片段{:03}。{}", i, "长".repeat(194)));
+    let mut list = dialogue(MS_TTS_DIALOGUE_MAX_VOICES + 5);
+    list.push(long(MS_TTS_DIALOGUE_MAX_VOICES + 5));
+    list.push(long(MS_TTS_DIALOGUE_MAX_VOICES + 6));
+    let result = MsTtsMsgRequest::merge_dialogue(list, 400);
+    let voices: Vec<usize> = result
+        .iter()
+        .map(|i| i.ssml.as_ref().unwrap().matches("<voice ").count())
+        .collect();
+    assert_eq!(voices, vec![MS_TTS_DIALOGUE_MAX_VOICES, 6, 1]);
+    check_order(&result, MS_TTS_DIALOGUE_MAX_VOICES + 7);
+
+    // 过长的片段先按长度切分
+    let text: String = (0..4).map(|i| format!("长片段第{}句。", i)).collect();
+    let result = MsTtsMsgRequest::merge_dialogue(
+        vec![segment(0, text), segment(1, "结束。".to_owned())],
+        17,
+    );
+    assert_eq!(result.len(), 2);
+    let ssml = result[1].ssml.as_ref().unwrap();
+    assert!(ssml.contains("长片段第2句。长片段第3句。"));
+    assert!(ssml.find("长片段第3句").unwrap() < ssml.find("结束。").unwrap());
+}
+
+/// 预留字符数测试
+#[test]
+fn test_reserve_chars() {
+    let mut request = build_request("zh-CN-XiaoxiaoNeural", "你好世界");
+    assert_eq!(request.reserve_chars(), request.billing_chars());
+    assert!(request.reserve_chars() > 0);
+    // 断开重试的请求不再重复预留
+    request.is_retry = true;
+    assert_eq!(request.reserve_chars(), 0);
+}
@@ -0,0 +1,737 @@
+use std::collections::HashSet;
+
+use crate::{
+    error::TTSServerError,
+    ms_tts::{
+        select_subscribe_key, MsTtsBoundary, MsTtsBoundaryType, MsTtsMsgError, SubscribeKeyHealth,
+    },
+    utils::{
+        audio::concat_audio,
+        azure_api::{
+            collating_list_of_pronouncers_arc, AzureApiRegionIdentifier, AzureSubscribeKey,
+            MsApiOrigin, Voice, VoicesFilter, VoicesItem, AZURE_SPEAKERS_LIST_FILE,
+            EDGE_SPEAKERS_LIST_FILE,
+        },
+        language::{detect_language, split_by_language},
+        lexicon::{apply_lexicon, LexiconEntry},
+        quota::{refund_subscribe_usage, try_reserve_subscribe_usage, SubscribeKeyUsage},
+        ssml::{
+            escape_xml, parse_prosody_contour, parse_prosody_pitch, parse_prosody_volume,
+            validate_ssml,
+        },
+        subtitle::{generate_subtitle, SubtitleFormat},
+        text::split_text,
+        text_rule::{TextRuleConfig, TextRuleSet},
+        voice_cache::{load_voices_cache, load_voices_list_from, save_voices_cache},
+        voice_preset::VoicePreset,
+    },
+    web::controller::{is_number_out_of_range, MsTtsMsgRequestJson, MsTtsSsmlRequestJson},
+};
+
+/// 长文本切分测试
+#[test]
+fn test_split_text() {
+    let text = "第一句话。第二句话！第三句话？";
+    assert_eq!(split_text(text, 100), vec![text.to_owned()]);
+    assert_eq!(
+        split_text(text, 6),
+        vec!["第一句话。", "第二句话！", "第三句话？"]
+    );
+
+    // 优先在段落处切分
+    let text = "第一段第一句。\n第二段。第二段第二句。";
+    assert_eq!(
+        split_text(text, 12),
+        vec!["第一段第一句。", "第二段。第二段第二句。"]
+    );
+
+    // 半角句号后没有空白时不切分
+    let text = "pi is 3.14159 and e is 2.71828. That is all.";
+    let list = split_text(text, 35);
+    assert_eq!(list.first().unwrap(), "pi is 3.14159 and e is 2.71828.");
+    assert_eq!(split_text("Wait!Go, run", 8), vec!["Wait!Go,", "run"]);
+
+    // 没有分隔符时按长度硬切
+    let list = split_text("abcdefghijk", 4);
+    assert_eq!(list, vec!["abcd", "efgh", "ijk"]);
+
+    // 每段都不超过最大长度
+    let text = "一二三四五六七八九十".repeat(30);
+    for chunk in split_text(&text, 32) {
+        assert!(chunk.chars().count() <= 32);
+    }
+}
+
+/// wav 音频拼接测试
+#[test]
+fn test_concat_audio_riff() {
+    fn wav(data: &[u8]) -> Vec<u8> {
+        let mut bin = Vec::new();
+        bin.extend_from_slice(b"RIFF");
+        bin.extend_from_slice(&((36 + data.len()) as u32).to_le_bytes());
+        bin.extend_from_slice(b"WAVEfmt ");
+        bin.extend_from_slice(&16u32.to_le_bytes());
+        bin.extend_from_slice(&[0u8; 16]);
+        bin.extend_from_slice(b"data");
+        bin.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bin.extend_from_slice(data);
+        bin
+    }
+    let result = concat_audio(
+        "riff-24khz-16bit-mono-pcm",
+        vec![wav(&[1, 2, 3, 4]), wav(&[5, 6])],
+    );
+    assert_eq!(result, wav(&[1, 2, 3, 4, 5, 6]));
+
+    let result = concat_audio("audio-24khz-48kbitrate-mono-mp3", vec![vec![1, 2], vec![3]]);
+    assert_eq!(result, vec![1, 2, 3]);
+}
+
+/// 字幕生成测试
+#[test]
+fn test_generate_subtitle() {
+    let word = |offset: u64, duration: u64, text: &str| MsTtsBoundary {
+        boundary_type: MsTtsBoundaryType::Word,
+        offset,
+        duration,
+        text: text.to_owned(),
+    };
+    let boundaries = vec![
+        word(500_000, 3_000_000, "Hello"),
+        word(3_600_000, 4_000_000, "world"),
+        // 停顿过长，另起一条字幕
+        word(25_000_000, 5_000_000, "你好"),
+        word(30_000_000, 5_000_000, "世界"),
+    ];
+
+    assert_eq!(
+        generate_subtitle(SubtitleFormat::Srt, &boundaries),
+        "1\n00:00:00,050 --> 00:00:00,760\nHello world\n\n2\n00:00:02,500 --> 00:00:03,500\n你好世界\n\n"
+    );
+    assert_eq!(
+        generate_subtitle(SubtitleFormat::WebVtt, &boundaries),
+        "WEBVTT\n\n00:00:00.050 --> 00:00:00.760\nHello world\n\n00:00:02.500 --> 00:00:03.500\n你好世界\n\n"
+    );
+    assert_eq!(
+        generate_subtitle(SubtitleFormat::Lrc, &boundaries),
+        "[00:00.05]Hello world\n[00:02.50]你好世界\n"
+    );
+
+    // 存在句子边界时按句子生成
+    let mut boundaries = boundaries;
+    boundaries.push(MsTtsBoundary {
+        boundary_type: MsTtsBoundaryType::Sentence,
+        offset: 500_000,
+        duration: 35_000_000,
+        text: "Hello world, 你好世界".to_owned(),
+    });
+    assert_eq!(
+        generate_subtitle(SubtitleFormat::Lrc, &boundaries),
+        "[00:00.05]Hello world, 你好世界\n"
+    );
+    assert!(SubtitleFormat::try_from("ass").is_err());
+}
+
+/// audio.metadata 边界事件解析测试
+#[test]
+fn test_parse_metadata_message() {
+    let msg = concat!(
+        "X-RequestId:0123456789abcdef0123456789abcdef\r\n",
+        "Content-Type:application/json; charset=utf-8\r\n",
+        "Path:audio.metadata\r\n\r\n",
+        r#"{"Metadata": ["#,
+        r#"{"Type": "WordBoundary", "Data": {"Offset": 1000000, "Duration": 4375000, "text": {"Text": "Hello", "Length": 5, "BoundaryType": "WordBoundary"}}},"#,
+        r#"{"Type": "SentenceBoundary", "Data": {"Offset": 1000000, "Duration": 12250000, "text": {"Text": "Hello world.", "Length": 12, "BoundaryType": "SentenceBoundary"}}},"#,
+        r#"{"Type": "SessionEnd", "Data": {"Offset": 13250000}}"#,
+        r#"]}"#
+    );
+    let boundaries = MsTtsBoundary::from_metadata_message(msg);
+    assert_eq!(
+        boundaries,
+        vec![
+            MsTtsBoundary {
+                boundary_type: MsTtsBoundaryType::Word,
+                offset: 1_000_000,
+                duration: 4_375_000,
+                text: "Hello".to_owned(),
+            },
+            MsTtsBoundary {
+                boundary_type: MsTtsBoundaryType::Sentence,
+                offset: 1_000_000,
+                duration: 12_250_000,
+                text: "Hello world.".to_owned(),
+            },
+        ]
+    );
+    // 偏移及时长单位为 100 纳秒，生成字幕时换算为毫秒
+    assert_eq!(
+        generate_subtitle(SubtitleFormat::Srt, &boundaries),
+        "1\n00:00:00,100 --> 00:00:01,325\nHello world.\n\n"
+    );
+
+    // 没有消息体或无法解析时返回空列表
+    assert!(MsTtsBoundary::from_metadata_message("Path:audio.metadata").is_empty());
+    assert!(MsTtsBoundary::from_metadata_message("Path:audio.metadata\r\n\r\n{").is_empty());
+}
+
+/// SSML 校验测试
+#[test]
+fn test_validate_ssml() {
+    let voices: HashSet<String> = ["zh-CN-XiaoxiaoNeural", "zh-CN-YunxiNeural"]
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+
+    let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xml:lang="zh-CN">
+        <voice name="zh-CN-XiaoxiaoNeural">你好<break time="500ms"/></voice>
+        <voice name="zh-CN-YunxiNeural">你好</voice>
+        <voice name="zh-CN-XiaoxiaoNeural">再见</voice>
+    </speak>"#;
+    assert_eq!(
+        validate_ssml(ssml, &voices).unwrap(),
+        vec!["zh-CN-XiaoxiaoNeural", "zh-CN-YunxiNeural"]
+    );
+
+    // 发音人不存在
+    let ssml = r#"<speak><voice name="en-US-JennyNeural">hi</voice></speak>"#;
+    assert!(validate_ssml(ssml, &voices).is_err());
+    // 元素未闭合
+    let ssml = r#"<speak><voice name="zh-CN-XiaoxiaoNeural">hi</voice>"#;
+    assert!(validate_ssml(ssml, &voices).is_err());
+    // 标签不匹配
+    let ssml = r#"<speak><voice name="zh-CN-XiaoxiaoNeural">hi</prosody></speak>"#;
+    assert!(validate_ssml(ssml, &voices).is_err());
+    // 根元素错误
+    let ssml = r#"<voice name="zh-CN-XiaoxiaoNeural">hi</voice>"#;
+    assert!(validate_ssml(ssml, &voices).is_err());
+    // 缺少 voice 元素
+    assert!(validate_ssml("<speak>hi</speak>", &voices).is_err());
+}
+
+#[test]
+fn test_parse_prosody() {
+    assert_eq!(parse_prosody_pitch("1").unwrap(), "+0%");
+    assert_eq!(parse_prosody_pitch("1.5").unwrap(), "+25%");
+    assert_eq!(parse_prosody_pitch("5").unwrap(), "+50%");
+    assert_eq!(parse_prosody_pitch("200Hz").unwrap(), "200Hz");
+    assert_eq!(parse_prosody_pitch("-20hz").unwrap(), "-20Hz");
+    assert_eq!(parse_prosody_pitch("2st").unwrap(), "+2st");
+    assert_eq!(parse_prosody_pitch("X-High").unwrap(), "x-high");
+    assert!(parse_prosody_pitch("0Hz").is_none());
+    assert!(parse_prosody_pitch("loud").is_none());
+
+    assert_eq!(parse_prosody_volume("0").unwrap(), "-100%");
+    assert_eq!(parse_prosody_volume("+10%").unwrap(), "+10%");
+    assert_eq!(parse_prosody_volume("soft").unwrap(), "soft");
+    assert!(parse_prosody_volume("abc").is_none());
+
+    assert_eq!(
+        parse_prosody_contour("(0%,+20Hz)  ( 50% , -2st )").unwrap(),
+        "(0%,+20Hz) (50%,-2st)"
+    );
+    assert!(parse_prosody_contour("(120%,+20Hz)").is_none());
+    assert!(parse_prosody_contour("(0%,+20Hz) x").is_none());
+}
+
+#[test]
+fn test_apply_lexicon() {
+    let entries = vec![
+        LexiconEntry {
+            grapheme: "TTS".to_owned(),
+            alphabet: None,
+            phoneme: None,
+            alias: Some("文本转语音".to_owned()),
+        },
+        LexiconEntry {
+            grapheme: "lt".to_owned(),
+            alphabet: Some("sapi".to_owned()),
+            phoneme: Some("l t".to_owned()),
+            alias: None,
+        },
+    ];
+    assert_eq!(
+        apply_lexicon("TTS 服务", "zh-CN-XiaoxiaoNeural", &entries),
+        "<sub alias='文本转语音'>TTS</sub> 服务"
+    );
+    // 不替换单词的一部分以及已转义的实体
+    assert_eq!(
+        apply_lexicon("TTSS &lt; lt", "en-US-JennyNeural", &entries),
+        "TTSS &lt; <phoneme alphabet='sapi' ph='l t'>lt</phoneme>"
+    );
+}
+
+#[test]
+fn test_text_rule() {
+    let config: TextRuleConfig = serde_json::from_str(
+        r#"{
+            "rules": [
+                {"type": "skip", "pattern": "`[^`]*`"},
+                {"type": "regex", "pattern": "https?://\\S+", "replacement": ""},
+                {"type": "literal", "pattern": "本章完", "replacement": "", "locale": ["zh"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let rules = TextRuleSet::compile(config).unwrap();
+    assert_eq!(
+        rules.apply("你好，`a，b` https://example.com 本章完", "zh-CN"),
+        "你好, `a，b`  "
+    );
+    // 语言不匹配的规则不执行
+    assert_eq!(rules.apply("本章完。", "en-US"), "本章完. ");
+}
+
+#[test]
+fn test_escape_xml() {
+    assert_eq!(
+        escape_xml("Tom & Jerry <say> \"hi\" 'ok'"),
+        "Tom &amp; Jerry &lt;say&gt; &quot;hi&quot; &apos;ok&apos;"
+    );
+    // 移除 XML 1.0 不允许的字符，保留换行与制表符
+    assert_eq!(escape_xml("a\u{0}b\u{1b}c\u{7f}\u{85}d\u{FFFF}"), "abcd");
+    assert_eq!(escape_xml("第一行\n\t第二行\r\n"), "第一行\n\t第二行\r\n");
+
+    let voices_name_list = HashSet::from(["zh-CN-XiaoxiaoNeural".to_owned()]);
+    let ssml = format!(
+        "<speak><voice name='zh-CN-XiaoxiaoNeural'>{}</voice></speak>",
+        escape_xml("A&B <c> \u{8}'d'")
+    );
+    assert!(validate_ssml(&ssml, &voices_name_list).is_ok());
+}
+
+#[test]
+fn test_split_by_language() {
+    assert_eq!(detect_language("这是一段中文 with English"), Some("zh"));
+    assert_eq!(detect_language("日本語のテキスト"), Some("ja"));
+    assert_eq!(detect_language("12345 ..."), None);
+
+    // 较短的英文单词不单独切分
+    assert_eq!(
+        split_by_language("我的 iPhone 手机"),
+        vec![("zh", "我的 iPhone 手机".to_owned())]
+    );
+    assert_eq!(
+        split_by_language("请跟读：This is a book. 这是一本书。"),
+        vec![
+            ("zh", "请跟读：".to_owned()),
+            ("en", "This is a book. ".to_owned()),
+            ("zh", "这是一本书。".to_owned()),
+        ]
+    );
+    assert!(split_by_language("123 !!!").is_empty());
+}
+
+/// 发音人预设解析测试
+#[test]
+fn test_voice_preset() {
+    let data = r#"{
+        "narrator": {"desc": "旁白", "informant": "zh-CN-YunxiNeural", "rate": 1.1, "pitch": 1.2},
+        "loud": {"volume": "+20%"}
+    }"#;
+    let presets: std::collections::BTreeMap<String, VoicePreset> =
+        serde_json::from_str(data).unwrap();
+    let narrator = presets.get("narrator").unwrap();
+    assert_eq!(narrator.desc, "旁白");
+    assert_eq!(narrator.informant.as_deref(), Some("zh-CN-YunxiNeural"));
+    assert_eq!(narrator.pitch.as_deref(), Some("1.2"));
+    assert_eq!(narrator.style, None);
+    let loud = presets.get("loud").unwrap();
+    assert_eq!(loud.volume.as_deref(), Some("+20%"));
+    assert_eq!(loud.rate, None);
+}
+
+/// 发音人筛选测试
+#[test]
+fn test_voices_filter() {
+    let list: Vec<VoicesItem> = serde_json::from_slice(EDGE_SPEAKERS_LIST_FILE).unwrap();
+    let list: Vec<_> = list.into_iter().map(std::sync::Arc::new).collect();
+    let voices_list = collating_list_of_pronouncers_arc(&list);
+
+    let filter = VoicesFilter {
+        locale: Some("zh-cn".to_owned()),
+        gender: Some("female".to_owned()),
+        ..Default::default()
+    };
+    let result = voices_list.filter(&filter);
+    assert!(!result.is_empty());
+    assert!(result
+        .iter()
+        .all(|i| i.get_local() == "zh-CN" && i.get_gender() == "Female"));
+
+    let filter = VoicesFilter {
+        language: Some("en".to_owned()),
+        name: Some("jenny".to_owned()),
+        ..Default::default()
+    };
+    let result = voices_list.filter(&filter);
+    assert!(result
+        .iter()
+        .any(|i| i.get_short_name() == "en-US-JennyNeural"));
+    assert!(result.iter().all(|i| i.get_local().starts_with("en-")));
+
+    assert_eq!(
+        voices_list.filter(&VoicesFilter::default()).len(),
+        list.len()
+    );
+}
+
+/// 统一发音人数据测试
+#[test]
+fn test_voice_normalize() {
+    let edge: Vec<VoicesItem> = serde_json::from_slice(EDGE_SPEAKERS_LIST_FILE).unwrap();
+    let azure: Vec<VoicesItem> = serde_json::from_slice(AZURE_SPEAKERS_LIST_FILE).unwrap();
+    let name = "zh-CN-XiaoxiaoNeural";
+    let edge_item = edge.iter().find(|i| i.get_short_name() == name).unwrap();
+    let azure_item = azure.iter().find(|i| i.get_short_name() == name).unwrap();
+
+    // edge 接口不支持风格，ContentCategories 不应作为风格返回
+    assert_eq!(edge_item.get_style(), None);
+    assert!(edge_item == azure_item);
+
+    let edge_voice = Voice::from(edge_item);
+    assert_eq!(edge_voice.name, name);
+    assert_eq!(edge_voice.locale, "zh-CN");
+    assert!(edge_voice.styles.is_empty());
+    assert!(!edge_voice.content_categories.is_empty());
+    assert!(!edge_voice.capabilities.style);
+
+    let azure_voice = Voice::from(azure_item);
+    assert_eq!(azure_voice.name, name);
+    assert_eq!(azure_voice.gender, edge_voice.gender);
+    assert!(!azure_voice.styles.is_empty());
+    assert!(azure_voice.capabilities.style);
+    assert!(azure_voice.sample_rate_hertz.is_some());
+}
+
+/// 订阅key字符用量测试
+#[test]
+fn test_subscribe_key_usage() {
+    let mut usage = SubscribeKeyUsage::default();
+    usage.add("2023-01", 300);
+    usage.add("2023-01", 200);
+    assert_eq!(usage.used_in("2023-01"), 500);
+    assert!(usage.has_budget("2023-01", 600, 100));
+    assert!(!usage.has_budget("2023-01", 600, 101));
+    // 额度为 0 时不限制
+    assert!(usage.has_budget("2023-01", 0, 10000));
+
+    // 进入新的月份重新计数
+    assert_eq!(usage.used_in("2023-02"), 0);
+    assert!(usage.has_budget("2023-02", 600, 600));
+    usage.add("2023-02", 100);
+    assert_eq!(usage.used_in("2023-02"), 100);
+    assert_eq!(usage.used_in("2023-01"), 0);
+
+    // 退回预留的字符数，不会小于 0，已进入新的月份时不处理
+    usage.refund("2023-02", 40);
+    assert_eq!(usage.used_in("2023-02"), 60);
+    usage.refund("2023-02", 100);
+    assert_eq!(usage.used_in("2023-02"), 0);
+    usage.add("2023-02", 50);
+    usage.refund("2023-01", 50);
+    assert_eq!(usage.used_in("2023-02"), 50);
+
+    // 前缀相同的 key 分别计数，标识不随程序版本变化
+    let region = AzureApiRegionIdentifier::from("eastasia").unwrap();
+    let key_a = AzureSubscribeKey("prefix00-key-a".to_owned(), region.clone(), Some(100));
+    let key_b = AzureSubscribeKey("prefix00-key-b".to_owned(), region, Some(100));
+    assert_ne!(key_a.usage_id(), key_b.usage_id());
+    assert_eq!(
+        key_a.usage_id(),
+        "dd5a4f8ab768d32e5291a67b094c1954f7f587e0dba024288dddf2e070ec2fd5"
+    );
+    assert!(try_reserve_subscribe_usage(&key_a.usage_id(), 100, 100));
+    assert!(!try_reserve_subscribe_usage(&key_a.usage_id(), 100, 1));
+    assert!(try_reserve_subscribe_usage(&key_b.usage_id(), 100, 100));
+}
+
+/// 严格校验模式测试
+#[test]
+fn test_strict_validation() {
+    let list: Vec<VoicesItem> = serde_json::from_slice(AZURE_SPEAKERS_LIST_FILE).unwrap();
+    let list: Vec<_> = list.into_iter().map(std::sync::Arc::new).collect();
+    let voices_list = collating_list_of_pronouncers_arc(&list);
+
+    let build = |value: serde_json::Value| {
+        let mut value = value;
+        value["text"] = "测试".into();
+        value["strict"] = true.into();
+        let json: MsTtsMsgRequestJson = serde_json::from_value(value).unwrap();
+        json.build_ms_request_with_voices(MsApiOrigin::Subscription, &voices_list, "id".to_owned())
+    };
+    let invalid_fields = |value: serde_json::Value| -> Vec<String> {
+        let err = build(value).unwrap_err();
+        assert_eq!(err.http_status, actix_web::http::StatusCode::BAD_REQUEST);
+        err.data
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["field"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    // 合法的参数
+    let request = build(serde_json::json!({
+        "informant": "zh-CN-XiaomoNeural",
+        "style": "Cheerful",
+        "role": "girl",
+        "rate": 1.5,
+        "pitch": "+10%",
+        "quality": "riff-24khz-16bit-mono-pcm",
+    }))
+    .unwrap();
+    assert_eq!(request.style, "cheerful");
+    assert_eq!(request.role.as_deref(), Some("Girl"));
+
+    // 未知的发音人，此时不再校验风格及角色
+    let err =
+        build(serde_json::json!({"informant": "zh-CN-UnknownNeural", "style": "x"})).unwrap_err();
+    let data = err.data.unwrap();
+    assert_eq!(data[0]["field"], "informant");
+    assert_eq!(data[0]["value"], "zh-CN-UnknownNeural");
+    assert!(data[0]["allowed"]
+        .as_array()
+        .unwrap()
+        .contains(&"zh-CN-XiaomoNeural".into()));
+    assert_eq!(data.as_array().unwrap().len(), 1);
+
+    // 发音人不支持的风格及角色
+    assert_eq!(
+        invalid_fields(serde_json::json!({
+            "informant": "zh-CN-XiaomoNeural",
+            "style": "whispering",
+            "role": "Robot",
+        })),
+        vec!["style", "role"]
+    );
+
+    // 超出范围的语速、音调及不支持的音频格式
+    assert_eq!(
+        invalid_fields(serde_json::json!({
+            "rate": 3.5,
+            "pitch": "2.5",
+            "quality": "audio-unknown",
+        })),
+        vec!["rate", "pitch", "quality"]
+    );
+    assert_eq!(
+        invalid_fields(serde_json::json!({"pitch": "very-high"})),
+        vec!["pitch"]
+    );
+
+    // 非严格模式下使用默认值
+    let json: MsTtsMsgRequestJson = serde_json::from_value(serde_json::json!({
+        "text": "测试",
+        "strict": false,
+        "rate": 3.5,
+        "quality": "audio-unknown",
+    }))
+    .unwrap();
+    let request = json
+        .build_ms_request_with_voices(MsApiOrigin::Subscription, &voices_list, "id".to_owned())
+        .unwrap();
+    assert_eq!(request.rate, "200");
+    assert_eq!(request.quality, "audio-24khz-48kbitrate-mono-mp3");
+
+    // 只判断倍数形式的参数
+    assert!(!is_number_out_of_range("1.5", 0.0, 2.0));
+    assert!(!is_number_out_of_range("2", 0.0, 2.0));
+    assert!(is_number_out_of_range("2.1", 0.0, 2.0));
+    assert!(is_number_out_of_range("-0.1", 0.0, 2.0));
+    assert!(!is_number_out_of_range("+10%", 0.0, 2.0));
+    assert!(!is_number_out_of_range("x-high", 0.0, 2.0));
+}
+
+/// SSML 多发音人校验测试
+#[test]
+fn test_ssml_multi_voice() {
+    let list: Vec<VoicesItem> = serde_json::from_slice(AZURE_SPEAKERS_LIST_FILE).unwrap();
+    let list: Vec<_> = list.into_iter().map(std::sync::Arc::new).collect();
+    let voices_list = collating_list_of_pronouncers_arc(&list);
+
+    let build = |api_name: MsApiOrigin, ssml: &str| {
+        let json: MsTtsSsmlRequestJson =
+            serde_json::from_value(serde_json::json!({ "ssml": ssml, "strict": true })).unwrap();
+        json.build_ms_request_with_voices(api_name, &voices_list, "id".to_owned())
+    };
+
+    let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xml:lang="zh-CN">
+        <voice name="zh-CN-XiaoxiaoNeural">你好</voice>
+        <voice name="zh-CN-YunxiNeural">你好</voice>
+    </speak>"#;
+    // 订阅接口支持多个发音人
+    let request = build(MsApiOrigin::Subscription, ssml).unwrap();
+    assert_eq!(request.informant, "zh-CN-XiaoxiaoNeural");
+    // edge 接口不支持多个发音人
+    let err = build(MsApiOrigin::EdgeFree, ssml).unwrap_err();
+    assert_eq!(err.http_status, actix_web::http::StatusCode::BAD_REQUEST);
+
+    // 同一发音人的多个 voice 元素不受限制
+    let ssml = r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xml:lang="zh-CN">
+        <voice name="zh-CN-XiaoxiaoNeural">你好</voice>
+        <voice name="zh-CN-XiaoxiaoNeural">再见</voice>
+    </speak>"#;
+    assert!(build(MsApiOrigin::EdgeFree, ssml).is_ok());
+}
+
+/// 发音人列表缓存测试
+#[tokio::test]
+async fn test_voices_cache() {
+    let dir = std::env::temp_dir().join(format!("tts-server-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let builtin: Vec<VoicesItem> = serde_json::from_slice(EDGE_SPEAKERS_LIST_FILE).unwrap();
+    let list: Vec<_> = serde_json::from_slice::<Vec<VoicesItem>>(AZURE_SPEAKERS_LIST_FILE)
+        .unwrap()
+        .into_iter()
+        .take(3)
+        .map(std::sync::Arc::new)
+        .collect();
+    let short_names = |list: &[std::sync::Arc<VoicesItem>]| -> Vec<String> {
+        list.iter().map(|i| i.get_short_name()).collect()
+    };
+    let failed = || async { Err(TTSServerError::ProgramError("请求失败".to_owned())) };
+
+    // 没有缓存时使用内置列表
+    assert!(load_voices_cache(&dir, "test").await.is_none());
+    let result = load_voices_list_from(&dir, true, "test", failed(), EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(result.len(), builtin.len());
+    let result =
+        load_voices_list_from(&dir, false, "test", failed(), EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(result.len(), builtin.len());
+
+    // 保存后读取的内容一致
+    save_voices_cache(&dir, "round-trip", &list).await;
+    let cache = load_voices_cache(&dir, "round-trip").await.unwrap();
+    assert_eq!(short_names(&cache), short_names(&list));
+    assert_eq!(cache[0].get_style(), list[0].get_style());
+
+    // 请求成功时使用请求结果并更新缓存
+    let request = async { Ok(list.clone()) };
+    let result = load_voices_list_from(&dir, false, "test", request, EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(short_names(&result), short_names(&list));
+    let cache = load_voices_cache(&dir, "test").await.unwrap();
+    assert_eq!(short_names(&cache), short_names(&list));
+
+    // 请求失败时使用缓存
+    let result =
+        load_voices_list_from(&dir, false, "test", failed(), EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(short_names(&result), short_names(&list));
+
+    // 不更新发音人列表时不发起请求，直接使用缓存
+    let request = async { panic!("不应请求发音人列表") };
+    let result = load_voices_list_from(&dir, true, "test", request, EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(short_names(&result), short_names(&list));
+
+    // 缓存无法解析时使用内置列表
+    std::fs::write(dir.join("test.json"), "invalid").unwrap();
+    let result = load_voices_list_from(&dir, true, "test", failed(), EDGE_SPEAKERS_LIST_FILE).await;
+    assert_eq!(result.len(), builtin.len());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// 订阅key选择测试
+#[tokio::test]
+async fn test_select_subscribe_key() {
+    let list: Vec<AzureSubscribeKey> = (0..3)
+        .map(|i| {
+            AzureSubscribeKey(
+                format!("select-test-key-{}", i),
+                AzureApiRegionIdentifier::from("eastasia").unwrap(),
+                None,
+            )
+        })
+        .collect();
+    let hash_list: Vec<String> = list.iter().map(|i| i.hash_str()).collect();
+    let index = tokio::sync::Mutex::new(0);
+
+    // 按顺序轮流选择，最后一个 key 之后回到第一个
+    for i in [0, 1, 2, 0] {
+        let selected = select_subscribe_key(&list, &index, &[], 0, 0).await;
+        assert_eq!(selected, Ok(&list[i]));
+    }
+    *index.lock().await = 2;
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 0, 0).await,
+        Ok(&list[2])
+    );
+    assert_eq!(*index.lock().await, 0);
+
+    // 跳过本次请求已尝试过的 key
+    let tried_list = vec![hash_list[0].clone(), hash_list[1].clone()];
+    let selected = select_subscribe_key(&list, &index, &tried_list, 0, 0).await;
+    assert_eq!(selected, Ok(&list[2]));
+
+    // 跳过冷却中的 key
+    SubscribeKeyHealth::record_failure(&hash_list[1], "测试");
+    *index.lock().await = 1;
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 0, 0).await,
+        Ok(&list[2])
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 0, 0).await,
+        Ok(&list[0])
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 0, 0).await,
+        Ok(&list[2])
+    );
+
+    // 没有可用的 key
+    let tried_list = vec![hash_list[0].clone(), hash_list[2].clone()];
+    assert_eq!(
+        select_subscribe_key(&list, &index, &tried_list, 0, 0).await,
+        Err(MsTtsMsgError::Unavailable)
+    );
+
+    // 选中时预留字符用量，额度不足的 key 被跳过，退回后可以再次选择
+    let list: Vec<AzureSubscribeKey> = (0..2)
+        .map(|i| {
+            AzureSubscribeKey(
+                format!("budget{}-test-key", i),
+                AzureApiRegionIdentifier::from("eastasia").unwrap(),
+                Some(100),
+            )
+        })
+        .collect();
+    let index = tokio::sync::Mutex::new(0);
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 0).await,
+        Ok(&list[0])
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 0).await,
+        Ok(&list[1])
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 0).await,
+        Err(MsTtsMsgError::QuotaExceeded)
+    );
+    refund_subscribe_usage(&list[1].usage_id(), 60);
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 0).await,
+        Ok(&list[1])
+    );
+
+    // 未指定额度的 key 使用默认额度
+    let list = vec![AzureSubscribeKey(
+        "default-budget-test-key".to_owned(),
+        AzureApiRegionIdentifier::from("eastasia").unwrap(),
+        None,
+    )];
+    let index = tokio::sync::Mutex::new(0);
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 100).await,
+        Ok(&list[0])
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 100).await,
+        Err(MsTtsMsgError::QuotaExceeded)
+    );
+    assert_eq!(
+        select_subscribe_key(&list, &index, &[], 60, 0).await,
+        Ok(&list[0])
+    );
+}
@@ -0,0 +1,134 @@
+use log::warn;
+
+///
+/// 音频时长单位，与微软接口边界事件一致 (100 纳秒)
+pub const TICKS_PER_SECOND: u64 = 10_000_000;
+
+///
+/// 拼接同一音频格式的多段音频
+///
+/// - mp3 / raw 格式为连续的帧数据，直接拼接即可
+/// - riff (wav) 格式只保留第一段的文件头，并修正文件头中的长度字段
+/// - ogg 格式拼接后为合法的链式流
+/// - webm 格式无法简单拼接，部分播放器可能只播放第一段
+pub fn concat_audio(quality: &str, list: Vec<Vec<u8>>) -> Vec<u8> {
+    if list.len() == 1 {
+        return list.into_iter().next().unwrap();
+    }
+    if quality.starts_with("riff-") {
+        if let Some(data) = concat_riff(&list) {
+            return data;
+        }
+        warn!("wav 文件头解析失败，直接拼接音频数据");
+    } else if quality.starts_with("webm-") {
+        warn!("webm 格式音频分段拼接后部分播放器可能只能播放第一段，建议使用其他音频格式");
+    }
+    list.concat()
+}
+
+///
+/// 拼接 wav 音频
+fn concat_riff(list: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let (header_len, _) = riff_data_range(list.first()?)?;
+    let mut data = Vec::new();
+    for item in list.iter() {
+        let (start, end) = riff_data_range(item)?;
+        data.extend_from_slice(&item[start..end]);
+    }
+
+    let mut result = list.first()?[..header_len].to_vec();
+    let data_len = data.len() as u32;
+    // data 块长度
+    result[header_len - 4..header_len].copy_from_slice(&data_len.to_le_bytes());
+    result.extend_from_slice(&data);
+    // RIFF 块长度
+    let riff_len = (result.len() - 8) as u32;
+    result[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(result)
+}
+
+///
+/// wav 音频文件头长度，数据不足以解析文件头时返回 None
+pub fn riff_header_len(bin: &[u8]) -> Option<usize> {
+    riff_data_range(bin).map(|(start, _)| start)
+}
+
+///
+/// 根据音频格式获取 Content-Type
+pub fn audio_content_type(quality: &str) -> &'static str {
+    if quality.ends_with("-mp3") {
+        "audio/mpeg"
+    } else if quality.starts_with("ogg-") {
+        "audio/ogg"
+    } else if quality.starts_with("webm-") {
+        "audio/webm"
+    } else if quality.starts_with("riff-") {
+        "audio/x-wav"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+///
+/// 查找 wav 音频中 data 块的位置，返回 (音频数据起始位置, 音频数据结束位置)
+fn riff_data_range(bin: &[u8]) -> Option<(usize, usize)> {
+    if bin.len() < 12 || &bin[0..4] != b"RIFF" || &bin[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut index = 12;
+    while index + 8 <= bin.len() {
+        let id = &bin[index..index + 4];
+        let size = u32::from_le_bytes(bin[index + 4..index + 8].try_into().ok()?) as usize;
+        let start = index + 8;
+        if id == b"data" {
+            // 流式输出时 data 块长度可能未填写
+            let end = if size == 0 || size == u32::MAX as usize {
+                bin.len()
+            } else {
+                (start + size).min(bin.len())
+            };
+            return Some((start, end));
+        }
+        index = start + size + size % 2;
+    }
+    None
+}
+
+///
+/// 根据音频格式估算音频时长，单位为 100 纳秒，无法根据格式计算码率时返回 None
+pub fn audio_duration_ticks(quality: &str, bin: &[u8]) -> Option<u64> {
+    let byte_rate = audio_byte_rate(quality)?;
+    let len = if quality.starts_with("riff-") {
+        riff_data_range(bin)
+            .map(|(start, end)| end - start)
+            .unwrap_or(bin.len())
+    } else {
+        bin.len()
+    };
+    Some(len as u64 * TICKS_PER_SECOND / byte_rate)
+}
+
+///
+/// 根据音频格式获取每秒字节数
+fn audio_byte_rate(quality: &str) -> Option<u64> {
+    let items = quality.split('-').collect::<Vec<&str>>();
+    // 压缩格式 如: audio-24khz-48kbitrate-mono-mp3 / audio-24khz-16bit-24kbps-mono-opus
+    if let Some(kbit) = items.iter().find_map(|i| {
+        i.strip_suffix("kbitrate")
+            .or_else(|| i.strip_suffix("kbps"))
+            .and_then(|k| k.parse::<u64>().ok())
+    }) {
+        return Some(kbit * 1000 / 8);
+    }
+    // 未压缩格式 如: riff-24khz-16bit-mono-pcm / raw-8khz-8bit-mono-alaw
+    if quality.ends_with("-pcm") || quality.ends_with("-alaw") || quality.ends_with("-mulaw") {
+        let khz = items
+            .iter()
+            .find_map(|i| i.strip_suffix("khz").and_then(|k| k.parse::<u64>().ok()))?;
+        let bit = items
+            .iter()
+            .find_map(|i| i.strip_suffix("bit").and_then(|k| k.parse::<u64>().ok()))?;
+        return Some(khz * 1000 * bit / 8);
+    }
+    None
+}
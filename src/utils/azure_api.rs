@@ -14,6 +14,7 @@ use log::{debug, error, info, trace, warn};
 use once_cell::sync::{Lazy, OnceCell};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::{
     net::TcpStream,
     sync::{Mutex, RwLock},
@@ -29,7 +30,16 @@ use tokio_tungstenite::{
     WebSocketStream,
 };
 
-use crate::{cmd::ServerArea, error::TTSServerError, random_string, AppArgs};
+use crate::{
+    cmd::ServerArea,
+    error::TTSServerError,
+    random_string,
+    utils::{
+        lexicon::LexiconEntry,
+        voice_cache::{load_voices_list, save_voices_cache, voices_cache_dir},
+    },
+    AppArgs,
+};
 
 // 发音人配置
 #[allow(dead_code)]
@@ -42,7 +52,7 @@ pub(crate) const EDGE_SPEAKERS_LIST_FILE: &[u8] =
     include_bytes!("../resource/edge_voices_list.json");
 
 /// 该程序实现的 Api 调用方式
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum MsApiOrigin {
     /// 传统 edge 免费预览接口
     EdgeFree,
@@ -50,6 +60,15 @@ pub enum MsApiOrigin {
     Subscription,
 }
 
+impl MsApiOrigin {
+    ///
+    /// 是否支持在同一个 SSML 文档中使用多个 voice 元素，edge 接口不支持
+    #[inline]
+    pub fn is_multi_voice(&self) -> bool {
+        matches!(self, MsApiOrigin::Subscription)
+    }
+}
+
 impl TryFrom<String> for MsApiOrigin {
     type Error = TTSServerError;
 
@@ -307,6 +326,8 @@ pub struct AzureSubscribeKey(
     pub String,
     /// 地域
     pub AzureApiRegionIdentifier,
+    /// 每月字符额度，未填写时使用 --subscribe-key-monthly-chars
+    pub Option<u64>,
 );
 
 impl AzureSubscribeKey {
@@ -316,6 +337,13 @@ impl AzureSubscribeKey {
         format!("{:x}", hasher.finish())
     }
 
+    ///
+    /// 持久化保存时使用的标识，为完整 key 及地域的 SHA-256 摘要，不受程序版本影响，也不会在文件中保存 key 原文
+    pub fn usage_id(&self) -> String {
+        let digest = Sha256::digest(format!("{}:{}", self.0, self.1.value()));
+        format!("{:x}", digest)
+    }
+
     pub fn from(list: &Vec<String>) -> Vec<Self> {
         let mut k_list = Vec::new();
         for i in list.iter() {
@@ -333,7 +361,7 @@ impl TryFrom<&str> for AzureSubscribeKey {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let l: Vec<_> = value.split(',').collect();
-        if l.len() != 2 {
+        if l.len() != 2 && l.len() != 3 {
             let err =
                 anyhow::Error::msg("错误的订阅字符串, 请检查订阅key参数是否符合要求".to_owned());
             warn!("{:?}", err);
@@ -342,7 +370,15 @@ impl TryFrom<&str> for AzureSubscribeKey {
         let key = l.first().unwrap().to_string();
         let region = l.get(1).unwrap().to_string();
         let region = AzureApiRegionIdentifier::from(&region)?;
-        Ok(AzureSubscribeKey(key, region))
+        let budget = match l.get(2) {
+            Some(budget) => Some(budget.trim().parse::<u64>().map_err(|e| {
+                let err = anyhow::Error::msg(format!("错误的订阅key字符额度 {} {:?}", budget, e));
+                warn!("{:?}", err);
+                err
+            })?),
+            None => None,
+        };
+        Ok(AzureSubscribeKey(key, region, budget))
     }
 }
 
@@ -354,7 +390,7 @@ impl Hash for AzureSubscribeKey {
 }
 
 /// 生成 xmml 的数据
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct MsTtsMsgRequest {
     // 待生成文本
     pub text: String,
@@ -364,10 +400,22 @@ pub struct MsTtsMsgRequest {
     pub informant: String,
     // 音频风格
     pub style: String,
+    // 角色扮演，仅部分订阅接口发音人支持
+    #[serde(default)]
+    pub role: Option<String>,
+    // 风格强度，范围 0.01 - 2
+    #[serde(default)]
+    pub style_degree: Option<String>,
     // 语速
     pub rate: String,
-    // 音调
+    // 音调，prosody 元素 pitch 属性的值
     pub pitch: String,
+    // 音量，prosody 元素 volume 属性的值
+    #[serde(default)]
+    pub volume: Option<String>,
+    // 音调曲线，prosody 元素 contour 属性的值
+    #[serde(default)]
+    pub contour: Option<String>,
     // 音频格式
     pub quality: String,
 
@@ -376,9 +424,29 @@ pub struct MsTtsMsgRequest {
     pub subscribe_key: Option<String>,
     #[serde(default)]
     pub region: Option<String>,
+    // 是否返回单词边界事件
+    #[serde(default)]
+    pub word_boundary: bool,
+    // 是否返回句子边界事件
+    #[serde(default)]
+    pub sentence_boundary: bool,
+    // 是否使用流式响应
+    #[serde(default)]
+    pub stream: bool,
+    // 连接断开后重新发送的请求，再次断开时不会重复重试
+    #[serde(default)]
+    pub is_retry: bool,
+    // 完整的 SSML 文档，存在时直接发送，不再使用模板生成
+    #[serde(default)]
+    pub ssml: Option<String>,
+    // 请求中指定的发音词典条目，优先于服务端词典
+    #[serde(default)]
+    pub phoneme_list: Vec<LexiconEntry>,
+    // 混合语言文本按语种切分后的片段，每个片段使用对应语种的发音人
+    #[serde(default)]
+    pub segments: Vec<MsTtsMsgRequest>,
     // 以前java版本支持的功能，目前没时间支持
     // text_replace_list:Vec<String>,
-    // phoneme_list:Vec<String>
 }
 
 ///
@@ -391,6 +459,92 @@ pub struct VoicesList {
     pub by_locale_map: HashMap<String, Vec<Arc<VoicesItem>>>,
 }
 
+///
+/// 发音人筛选条件，未填写的条件不参与筛选，除名称外均不区分大小写完整匹配
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct VoicesFilter {
+    /// 完整语言，如 zh-CN
+    pub locale: Option<String>,
+    /// 语种前缀，如 zh
+    pub language: Option<String>,
+    /// 性别，Female / Male
+    pub gender: Option<String>,
+    /// 发音人类型，Neural / Standard
+    pub voice_type: Option<String>,
+    /// 支持的风格
+    pub style: Option<String>,
+    /// 支持的角色扮演
+    pub role: Option<String>,
+    /// 名称，匹配发音人名称及描述中包含的文本
+    pub name: Option<String>,
+}
+
+impl VoicesList {
+    ///
+    /// 按条件筛选发音人，结果按名称排序。指定语言时只在对应语言的发音人中查找
+    pub fn filter(&self, filter: &VoicesFilter) -> Vec<Arc<VoicesItem>> {
+        let non_empty = |v: &Option<String>| {
+            v.as_deref()
+                .map(|i| i.trim().to_lowercase())
+                .filter(|i| !i.is_empty())
+        };
+        let locale = non_empty(&filter.locale);
+        let language = non_empty(&filter.language).map(|i| format!("{}-", i));
+        let gender = non_empty(&filter.gender);
+        let voice_type = non_empty(&filter.voice_type);
+        let style = non_empty(&filter.style);
+        let role = non_empty(&filter.role);
+        let name = non_empty(&filter.name);
+        let contains = |list: Option<Vec<String>>, value: &str| {
+            list.unwrap_or_default()
+                .iter()
+                .any(|i| i.eq_ignore_ascii_case(value))
+        };
+
+        let mut list: Vec<Arc<VoicesItem>> = self
+            .by_locale_map
+            .iter()
+            .filter(|(k, _)| {
+                let k = k.to_lowercase();
+                locale.as_ref().map(|i| &k == i).unwrap_or(true)
+                    && language
+                        .as_ref()
+                        .map(|i| k.starts_with(i.as_str()))
+                        .unwrap_or(true)
+            })
+            .flat_map(|(_, v)| v.iter())
+            .filter(|i| {
+                gender
+                    .as_ref()
+                    .map(|v| i.get_gender().eq_ignore_ascii_case(v))
+                    .unwrap_or(true)
+                    && voice_type
+                        .as_ref()
+                        .map(|v| i.get_voice_type().eq_ignore_ascii_case(v))
+                        .unwrap_or(true)
+                    && style
+                        .as_ref()
+                        .map(|v| contains(i.get_style(), v))
+                        .unwrap_or(true)
+                    && role
+                        .as_ref()
+                        .map(|v| contains(i.get_role(), v))
+                        .unwrap_or(true)
+                    && name
+                        .as_ref()
+                        .map(|v| {
+                            i.get_short_name().to_lowercase().contains(v)
+                                || i.get_desc().to_lowercase().contains(v)
+                        })
+                        .unwrap_or(true)
+            })
+            .cloned()
+            .collect();
+        list.sort_by_key(|i| i.get_short_name());
+        list
+    }
+}
+
 ///
 /// Azure 订阅版文本转语音官方相关接口实例
 pub(crate) struct AzureApiSubscribeToken {
@@ -414,8 +568,8 @@ static MS_TTS_SUBSCRIBE_TOKEN_LIST: Lazy<Arc<Mutex<HashMap<String, Arc<AzureApiS
         Arc::new(Mutex::new(kk))
     });
 
-static MS_TTS_SUBSCRIBE_VICES_MIXED_LIST: tokio::sync::OnceCell<Vec<Arc<VoicesItem>>> =
-    tokio::sync::OnceCell::const_new();
+static MS_TTS_SUBSCRIBE_VICES_MIXED_LIST: RwLock<Option<Vec<Arc<VoicesItem>>>> =
+    RwLock::const_new(None);
 
 impl AzureApiSubscribeToken {
     /// 过期时间
@@ -423,6 +577,25 @@ impl AzureApiSubscribeToken {
     /// 请求 user-agent
     const USER_AGENT: &'static str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/107.0.0.0 Safari/537.36 Edg/107.0.1379.1";
 
+    ///
+    /// 生成 SSML 文档，每个请求对应一个 voice 元素
+    pub(crate) fn generate_ssml(list: &[MsTtsMsgRequest]) -> String {
+        let mut ssml = String::from("<speak version='1.0' xmlns='http://www.w3.org/2001/10/synthesis' xmlns:mstts='https://www.w3.org/2001/mstts' xmlns:emo='http://www.w3.org/2009/10/emotionml' xml:lang='en-US'>");
+        for data in list {
+            let mut express_as = format!("style='{}'", data.style);
+            if let Some(style_degree) = &data.style_degree {
+                express_as.push_str(format!(" styledegree='{}'", style_degree).as_str());
+            }
+            if let Some(role) = &data.role {
+                express_as.push_str(format!(" role='{}'", role).as_str());
+            }
+            ssml.push_str(format!("<voice name='{}'><mstts:express-as {} ><prosody {}>{}</prosody></mstts:express-as></voice>",
+                                  data.informant, express_as, data.prosody_attributes(), data.ssml_text()).as_str());
+        }
+        ssml.push_str("</speak>");
+        ssml
+    }
+
     #[inline]
     pub(crate) fn hash_str(&self) -> String {
         let mut hasher = DefaultHasher::new();
@@ -479,48 +652,94 @@ impl AzureApiSubscribeToken {
 
     /// 获取程序中配置所有订阅key的发音人列表 注：列表只取交集，防止部分地区发音人在随机api中无法使用
     pub(crate) async fn get_vices_mixed_list() -> Result<VoicesList, TTSServerError> {
-        let list = MS_TTS_SUBSCRIBE_VICES_MIXED_LIST
-            .get_or_init(|| async move {
-                let list = Self::get_subscribe_key_list().await;
-                let mut resp = Vec::new();
-                for x in list {
-                    let kk = x.clone();
-                    let call = || async move { kk.get_vices_list().await };
-                    resp.push(call())
+        if let Some(list) = MS_TTS_SUBSCRIBE_VICES_MIXED_LIST.read().await.as_ref() {
+            return Ok(collating_list_of_pronouncers_arc(list));
+        }
+        let mut list = MS_TTS_SUBSCRIBE_VICES_MIXED_LIST.write().await;
+        if list.is_none() {
+            match Self::collect_vices_mixed_list().await {
+                Ok(d) => {
+                    list.replace(d);
                 }
-                let resp: Vec<Result<VoicesList, TTSServerError>> = join_all(resp).await;
-
-                let mut tmp: Option<Vec<Arc<VoicesItem>>> = None;
-
-                for re in resp {
-                    match re {
-                        Ok(d)=>{
-                            let d = d.raw_data;
-                            if let Some(t) = tmp {
-                                let intersect = t
-                                    .iter()
-                                    .filter(|&u| d.contains(u))
-                                    .cloned()
-                                    .collect::<Vec<_>>();
-                                tmp = Some(intersect);
-                            } else {
-                                tmp = Some(d.clone())
-                            }
-                        }
-                        Err(re)=>{
-                            error!("{:?}", re);
-                            std::process::exit(1);
-                        }
-                    };
+                Err(re) => {
+                    error!("{:?}", re);
+                    std::process::exit(1);
                 }
-                
-                tmp.unwrap()
-            })
-            .await;
-        let arc_list = collating_list_of_pronouncers_arc(list);
+            }
+        }
+        let arc_list = collating_list_of_pronouncers_arc(list.as_ref().unwrap());
         Ok(arc_list)
     }
 
+    /// 重新请求所有订阅key的发音人列表，并更新发音人列表交集
+    pub(crate) async fn refresh_vices_mixed_list() -> Result<(), TTSServerError> {
+        for x in Self::get_subscribe_key_list().await {
+            if let Err(e) = x.refresh_vices_list().await {
+                warn!(
+                    "刷新 {} 地域发音人列表失败 {:?}",
+                    x.region_identifier.value(),
+                    e
+                );
+            }
+        }
+        let list = Self::collect_vices_mixed_list().await?;
+        MS_TTS_SUBSCRIBE_VICES_MIXED_LIST
+            .write()
+            .await
+            .replace(list);
+        Ok(())
+    }
+
+    /// 计算所有订阅key发音人列表的交集
+    async fn collect_vices_mixed_list() -> Result<Vec<Arc<VoicesItem>>, TTSServerError> {
+        let list = Self::get_subscribe_key_list().await;
+        let mut resp = Vec::new();
+        for x in list {
+            let kk = x.clone();
+            let call = || async move { kk.get_vices_list().await };
+            resp.push(call())
+        }
+        let resp: Vec<Result<VoicesList, TTSServerError>> = join_all(resp).await;
+
+        let mut tmp: Option<Vec<Arc<VoicesItem>>> = None;
+
+        for re in resp {
+            let d = re?.raw_data;
+            if let Some(t) = tmp {
+                let intersect = t
+                    .iter()
+                    .filter(|&u| d.contains(u))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                tmp = Some(intersect);
+            } else {
+                tmp = Some(d.clone())
+            }
+        }
+
+        tmp.ok_or_else(|| TTSServerError::ProgramError("未配置订阅key".to_owned()))
+    }
+
+    /// 发音人列表缓存名称，同一地域的订阅key共用缓存
+    #[inline]
+    fn voices_cache_name(&self) -> String {
+        format!("subscribe-{}", self.region_identifier.value())
+    }
+
+    /// 请求订阅接口的发音人列表
+    async fn get_vices_list_request(&self) -> Result<Vec<Arc<VoicesItem>>, TTSServerError> {
+        let list = get_voices_list_by_authkey(self).await?;
+        Ok(list.into_iter().map(Arc::new).collect())
+    }
+
+    /// 重新请求发音人列表，成功后更新缓存
+    pub(crate) async fn refresh_vices_list(&self) -> Result<(), TTSServerError> {
+        let list = self.get_vices_list_request().await?;
+        save_voices_cache(&voices_cache_dir(), &self.voices_cache_name(), &list).await;
+        self.voices_list.write().await.replace(list);
+        Ok(())
+    }
+
     /// 判断认证 Token 是否过期
     #[inline]
     #[allow(dead_code)]
@@ -656,11 +875,6 @@ impl AzureApiSubscribeToken {
 
         Ok(websocket.0)
     }
-
-    // 以后的版本可能实现的功能，当拥有多个 api时，支持使用负载均衡处理，超过或接近免费额度则更换api
-    // pub async fn get_free_quota(self){
-    //     // https://management.azure.com/subscriptions/{subscription-id}/resourceGroups/{resource-group-name}/providers/{resource-provider-namespace}/{resource-type}/{resource-name}/providers/microsoft.insights/metrics?metricnames={metric}&timespan={starttime/endtime}&$filter={filter}&resultType=metadata&api-version={apiVersion}
-    // }
 }
 
 ///
@@ -706,18 +920,12 @@ impl AzureApiSpeakerList for AzureApiSubscribeToken {
     fn get_vices_list(&self) -> BoxFutureSync<Result<VoicesList, TTSServerError>> {
         Box::pin(async move {
             if self.voices_list.read().await.is_none() {
-                let mut voice_arc_list = Vec::new();
-                let kk = if let Ok(d) = get_voices_list_by_authkey(self).await {
-                    d
-                } else {
-                    warn!("请求 AzureApiSubscribeToken 发音人列表出错，改用缓存数据！");
-                    let data_str = String::from_utf8(AZURE_SPEAKERS_LIST_FILE.to_vec()).unwrap();
-                    let tmp_list_1: Vec<VoicesItem> = serde_json::from_str(&data_str).unwrap();
-                    tmp_list_1
-                };
-                for voice in kk {
-                    voice_arc_list.push(Arc::new(voice))
-                }
+                let voice_arc_list = load_voices_list(
+                    &self.voices_cache_name(),
+                    self.get_vices_list_request(),
+                    AZURE_SPEAKERS_LIST_FILE,
+                )
+                .await;
                 self.voices_list.write().await.replace(voice_arc_list);
             };
 
@@ -778,15 +986,18 @@ impl AzureApiGenerateXMML for AzureApiSubscribeToken {
             msg1.push_str(format!("X-RequestId: {}\r\n", data.request_id).as_str());
             msg1.push_str(format!("X-Timestamp: {}\r\n", time).as_str());
             msg1.push_str("Content-Type: application/json\r\n\r\n");
-            msg1.push_str(r#"{"synthesis":{"audio":{"metadataOptions":{"bookmarkEnabled":false,"sentenceBoundaryEnabled":false,"visemeEnabled":false,"wordBoundaryEnabled":false},"outputFormat":""#);
+            msg1.push_str(format!(r#"{{"synthesis":{{"audio":{{"metadataOptions":{{"bookmarkEnabled":false,"sentenceBoundaryEnabled":{},"visemeEnabled":false,"wordBoundaryEnabled":{}}},"outputFormat":""#, data.sentence_boundary, data.word_boundary).as_str());
             msg1.push_str(data.quality.as_str());
             msg1.push_str(r#""},"language":{"autoDetection":false}}}"#);
             xmml_data.push(msg1);
 
             let mut msg2 = String::new();
             msg2.push_str(format!("Path: ssml\r\nX-RequestId: {}\r\nX-Timestamp:{}\r\nContent-Type:application/ssml+xml\r\n\r\n", &data.request_id, &time).as_str());
-            msg2.push_str(format!("<speak version='1.0' xmlns='http://www.w3.org/2001/10/synthesis' xmlns:mstts='https://www.w3.org/2001/mstts' xmlns:emo='http://www.w3.org/2009/10/emotionml' xml:lang='en-US'><voice name='{}'><mstts:express-as style='{}' ><prosody rate ='{}%' pitch='{}%'>{}</prosody></mstts:express-as></voice></speak>",
-                                  data.informant, data.style, data.rate, data.pitch, data.text).as_str());
+            if let Some(ssml) = data.ssml.as_ref() {
+                msg2.push_str(ssml);
+            } else {
+                msg2.push_str(&Self::generate_ssml(std::slice::from_ref(&data)));
+            }
             xmml_data.push(msg2);
             Ok(xmml_data)
         })
@@ -845,6 +1056,12 @@ impl AzureApiEdgeFree {
 
     const USER_AGENT: &'static str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/102.0.5005.63 Safari/537.36 Edg/102.0.1245.39";
 
+    /// edge 接口固定的音频格式
+    pub(crate) const OUTPUT_FORMAT: &'static str = "audio-24khz-96kbitrate-mono-mp3";
+
+    /// 发音人列表缓存名称
+    const VOICES_CACHE_NAME: &'static str = "edge";
+
     #[allow(dead_code)]
     pub(crate) fn new() -> Arc<Self> {
         static INSTANCE: OnceCell<Arc<AzureApiEdgeFree>> = OnceCell::new();
@@ -1043,6 +1260,17 @@ impl AzureApiEdgeFree {
         }
     }
 
+    ///
+    /// edge 接口 speech.config 配置内容
+    fn speech_config(word_boundary: bool, sentence_boundary: bool) -> String {
+        format!(
+            r#"{{"context":{{"synthesis":{{"audio":{{"metadataoptions":{{"sentenceBoundaryEnabled":"{}","wordBoundaryEnabled":"{}"}},"outputFormat":"{}"}}}}}}}}"#,
+            sentence_boundary,
+            word_boundary,
+            Self::OUTPUT_FORMAT
+        )
+    }
+
     async fn get_vices_list_request() -> Result<Vec<Arc<VoicesItem>>, TTSServerError> {
         let url = format!(
             "https://speech.platform.bing.com/consumer/speech/synthesize/readaloud/voices/list?trustedclienttoken={}",
@@ -1098,6 +1326,14 @@ impl AzureApiEdgeFree {
 
         Ok(voice_arc_list)
     }
+
+    /// 重新请求发音人列表，成功后更新缓存
+    pub(crate) async fn refresh_vices_list(&self) -> Result<(), TTSServerError> {
+        let list = Self::get_vices_list_request().await?;
+        save_voices_cache(&voices_cache_dir(), Self::VOICES_CACHE_NAME, &list).await;
+        self.voices_list.write().await.replace(list);
+        Ok(())
+    }
 }
 
 /// 实现微软官网免费预览接口获取发音人列表
@@ -1106,18 +1342,12 @@ impl AzureApiSpeakerList for AzureApiEdgeFree {
     fn get_vices_list(&self) -> BoxFutureSync<Result<VoicesList, TTSServerError>> {
         Box::pin(async move {
             if self.voices_list.read().await.is_none() {
-                let kk = if let Ok(d) = Self::get_vices_list_request().await {
-                    d
-                } else {
-                    warn!("请求 AzureApiEdgeFree 发音人列表出错，改用缓存数据！");
-                    let data_str = String::from_utf8(EDGE_SPEAKERS_LIST_FILE.to_vec()).unwrap();
-                    let tmp_list_1: Vec<VoicesItem> = serde_json::from_str(&data_str).unwrap();
-                    let mut voice_arc_list = Vec::new();
-                    for voice in tmp_list_1 {
-                        voice_arc_list.push(Arc::new(voice))
-                    }
-                    voice_arc_list
-                };
+                let kk = load_voices_list(
+                    Self::VOICES_CACHE_NAME,
+                    Self::get_vices_list_request(),
+                    EDGE_SPEAKERS_LIST_FILE,
+                )
+                .await;
                 self.voices_list.write().await.replace(kk);
             };
 
@@ -1140,7 +1370,7 @@ impl AzureApiNewWebsocket for AzureApiEdgeFree {
             let time = format!("{:?}", create_time);
             msg1.push_str(format!("X-Timestamp: {}\r\nContent-Type: application/json; charset=utf-8\r\nPath: speech.config", &time).as_str());
             msg1.push_str("\r\n\r\n");
-            msg1.push_str(r#"{"context":{"synthesis":{"audio":{"metadataoptions":{"sentenceBoundaryEnabled":"false","wordBoundaryEnabled":"false"},"outputFormat":"audio-24khz-96kbitrate-mono-mp3"}}}}"#);
+            msg1.push_str(&Self::speech_config(false, false));
             // xmml_data.push(msg1);
             new_socket
                 .send(tungstenite::Message::Text(msg1))
@@ -1164,10 +1394,24 @@ impl AzureApiGenerateXMML for AzureApiEdgeFree {
             let mut xmml_data = Vec::new();
             let create_time = Utc::now();
             let time = format!("{:?}", create_time);
+            // edge 接口的边界事件开关只能通过 speech.config 设置且对整个连接生效，所以每个请求前都重新发送
+            let mut msg1 = String::new();
+            msg1.push_str(format!("X-Timestamp: {}\r\nContent-Type: application/json; charset=utf-8\r\nPath: speech.config", &time).as_str());
+            msg1.push_str("\r\n\r\n");
+            msg1.push_str(&Self::speech_config(
+                data.word_boundary,
+                data.sentence_boundary,
+            ));
+            xmml_data.push(msg1);
+            // edge 接口不支持 mstts:express-as，风格参数在接口层忽略并通过响应头提示
             let mut msg2 = String::new();
             msg2.push_str(format!("X-RequestId:{}\r\nContent-Type:application/ssml+xml\r\nX-Timestamp:{}\r\nPath:ssml\r\n\r\n", &data.request_id, &time).as_str());
-            msg2.push_str(format!("<speak version='1.0' xmlns='http://www.w3.org/2001/10/synthesis' xmlns:mstts='https://www.w3.org/2001/mstts' xml:lang='en-US'><voice name='{}'><prosody pitch='+0Hz' rate ='{}%' volume='+0%'>{}</prosody></voice></speak>",
-                                  data.informant, data.rate, data.text).as_str());
+            if let Some(ssml) = data.ssml.as_ref() {
+                msg2.push_str(ssml);
+            } else {
+                msg2.push_str(format!("<speak version='1.0' xmlns='http://www.w3.org/2001/10/synthesis' xmlns:mstts='https://www.w3.org/2001/mstts' xml:lang='en-US'><voice name='{}'><prosody {}>{}</prosody></voice></speak>",
+                                      data.informant, data.prosody_attributes(), data.ssml_text()).as_str());
+            }
             xmml_data.push(msg2);
             Ok(xmml_data)
         })
@@ -1332,13 +1576,41 @@ impl VoicesItem {
         };
     }
     #[inline]
+    pub fn get_gender(&self) -> &str {
+        match self {
+            VoicesItem::AzureApi { gender, .. } => gender.as_str(),
+            VoicesItem::EdgeApi { gender, .. } => gender.as_str(),
+        }
+    }
+
+    ///
+    /// 发音人类型，edge 接口只提供神经网络发音人
+    #[inline]
+    pub fn get_voice_type(&self) -> &str {
+        match self {
+            VoicesItem::AzureApi { voice_type, .. } => voice_type.as_str(),
+            VoicesItem::EdgeApi { .. } => "Neural",
+        }
+    }
+
+    ///
+    /// 获取发音人支持的风格列表，edge 接口不支持风格，VoiceTag 中的 ContentCategories 是适用的内容类型而不是风格
+    #[inline]
     pub fn get_style(&self) -> Option<Vec<String>> {
-        return match self {
+        match self {
             VoicesItem::AzureApi { style_list, .. } => style_list.clone(),
-            VoicesItem::EdgeApi { voice_tag, .. } => {
-                voice_tag.get("ContentCategories").cloned()
-            }
-        };
+            VoicesItem::EdgeApi { .. } => None,
+        }
+    }
+
+    ///
+    /// 获取发音人支持的角色扮演列表，edge 接口不支持
+    #[inline]
+    pub fn get_role(&self) -> Option<Vec<String>> {
+        match self {
+            VoicesItem::AzureApi { role_play_list, .. } => role_play_list.clone(),
+            VoicesItem::EdgeApi { .. } => None,
+        }
     }
 
     pub fn get_desc(&self) -> String {
@@ -1363,38 +1635,141 @@ impl VoicesItem {
     }
 }
 
+///
+/// 不同接口返回的同一个发音人视为相同
 impl PartialEq for VoicesItem {
     #[inline]
     fn eq(&self, other: &Self) -> bool {
-        match self {
+        self.get_short_name() == other.get_short_name()
+    }
+}
+
+///
+/// 与接口无关的发音人数据，web 接口默认返回该格式
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Voice {
+    /// 发音人名称，如 zh-CN-XiaoxiaoNeural
+    pub name: String,
+    /// 发音人描述
+    pub display_name: String,
+    /// 发音人在对应语言中的名称，edge 接口没有该数据
+    pub local_name: Option<String>,
+    /// 语言，如 zh-CN
+    pub locale: String,
+    /// 语言名称，edge 接口没有该数据
+    pub locale_name: Option<String>,
+    /// 性别
+    pub gender: String,
+    /// 发音人类型，Neural / Standard
+    pub voice_type: String,
+    /// 支持的风格
+    pub styles: Vec<String>,
+    /// 支持的角色扮演
+    pub roles: Vec<String>,
+    /// 采样率
+    pub sample_rate_hertz: Option<u32>,
+    /// 状态，如 GA / Preview
+    pub status: String,
+    /// 每分钟朗读的单词数
+    pub words_per_minute: Option<u32>,
+    /// 适用的内容类型，仅 edge 接口提供
+    pub content_categories: Vec<String>,
+    /// 声音特点，仅 edge 接口提供
+    pub personalities: Vec<String>,
+    /// 发音人支持的功能
+    pub capabilities: VoiceCapabilities,
+}
+
+///
+/// 发音人支持的功能
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct VoiceCapabilities {
+    /// 是否支持风格
+    pub style: bool,
+    /// 是否支持风格强度
+    pub style_degree: bool,
+    /// 是否支持角色扮演
+    pub role: bool,
+    /// 是否支持在同一次请求中与其他发音人混合使用
+    pub multi_voice: bool,
+}
+
+impl From<&VoicesItem> for Voice {
+    fn from(value: &VoicesItem) -> Self {
+        let styles = value.get_style().unwrap_or_default();
+        let roles = value.get_role().unwrap_or_default();
+        match value {
             VoicesItem::AzureApi {
-                short_name: short_name1,
+                short_name,
+                display_name,
+                local_name,
+                locale,
+                locale_name,
+                gender,
+                voice_type,
+                sample_rate_hertz,
+                status,
+                words_per_minute,
                 ..
-            } => {
-                if let VoicesItem::AzureApi {
-                    short_name: short_name2,
-                    ..
-                } = other
-                {
-                    short_name1 == short_name2
-                } else {
-                    false
-                }
-            }
+            } => Voice {
+                name: short_name.clone(),
+                display_name: display_name.clone(),
+                local_name: Some(local_name.clone()),
+                locale: locale.clone(),
+                locale_name: Some(locale_name.clone()),
+                gender: gender.clone(),
+                voice_type: voice_type.clone(),
+                capabilities: VoiceCapabilities {
+                    style: !styles.is_empty(),
+                    style_degree: !styles.is_empty(),
+                    role: !roles.is_empty(),
+                    multi_voice: MsApiOrigin::Subscription.is_multi_voice(),
+                },
+                styles,
+                roles,
+                sample_rate_hertz: sample_rate_hertz.parse().ok(),
+                status: status.clone(),
+                words_per_minute: words_per_minute.as_ref().and_then(|i| i.parse().ok()),
+                content_categories: Vec::new(),
+                personalities: Vec::new(),
+            },
             VoicesItem::EdgeApi {
-                short_name: short_name1,
+                short_name,
+                locale,
+                gender,
+                friendly_name,
+                status,
+                voice_tag,
                 ..
-            } => {
-                if let VoicesItem::EdgeApi {
-                    short_name: short_name2,
-                    ..
-                } = other
-                {
-                    short_name1 == short_name2
-                } else {
-                    false
-                }
-            }
+            } => Voice {
+                name: short_name.clone(),
+                display_name: friendly_name.clone(),
+                local_name: None,
+                locale: locale.clone(),
+                locale_name: None,
+                gender: gender.clone(),
+                voice_type: value.get_voice_type().to_owned(),
+                capabilities: VoiceCapabilities {
+                    style: false,
+                    style_degree: false,
+                    role: false,
+                    multi_voice: MsApiOrigin::EdgeFree.is_multi_voice(),
+                },
+                styles,
+                roles,
+                // edge 接口的音频格式固定为 24khz
+                sample_rate_hertz: Some(24000),
+                status: status.clone(),
+                words_per_minute: None,
+                content_categories: voice_tag
+                    .get("ContentCategories")
+                    .cloned()
+                    .unwrap_or_default(),
+                personalities: voice_tag
+                    .get("VoicePersonalities")
+                    .cloned()
+                    .unwrap_or_default(),
+            },
         }
     }
 }
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use crate::utils::azure_api::{VoicesItem, VoicesList};
+
+///
+/// 各语种默认使用的语言及发音人，发音人不存在时使用该语言的第一个发音人
+const LANGUAGE_DEFAULT_VOICE_LIST: [(&str, &str, &str); 10] = [
+    ("zh", "zh-CN", "zh-CN-XiaoxiaoNeural"),
+    ("en", "en-US", "en-US-JennyNeural"),
+    ("ja", "ja-JP", "ja-JP-NanamiNeural"),
+    ("ko", "ko-KR", "ko-KR-SunHiNeural"),
+    ("ru", "ru-RU", "ru-RU-SvetlanaNeural"),
+    ("ar", "ar-SA", "ar-SA-ZariyahNeural"),
+    ("th", "th-TH", "th-TH-PremwadeeNeural"),
+    ("hi", "hi-IN", "hi-IN-SwaraNeural"),
+    ("el", "el-GR", "el-GR-AthinaNeural"),
+    ("he", "he-IL", "he-IL-HilaNeural"),
+];
+
+///
+/// 拉丁字母片段少于该单词数时并入相邻片段，其他语言的发音人一般可以朗读少量英文单词
+const LATIN_SEGMENT_MIN_WORDS: usize = 3;
+
+///
+/// 根据字符所属文字判断语种，标点、数字、空白等不区分语种的字符返回 None
+fn char_language(c: char) -> Option<&'static str> {
+    match c as u32 {
+        0x3040..=0x30FF | 0x31F0..=0x31FF => Some("ja"),
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Some("ko"),
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => Some("zh"),
+        0x0400..=0x04FF => Some("ru"),
+        0x0600..=0x06FF => Some("ar"),
+        0x0E00..=0x0E7F => Some("th"),
+        0x0900..=0x097F => Some("hi"),
+        0x0370..=0x03FF => Some("el"),
+        0x0590..=0x05FF => Some("he"),
+        0x00C0..=0x024F => Some("en"),
+        _ if c.is_ascii_alphabetic() => Some("en"),
+        _ => None,
+    }
+}
+
+///
+/// 文本中出现假名时，汉字按日语处理
+fn text_char_language(text: &str) -> impl Fn(char) -> Option<&'static str> {
+    let has_kana = text.chars().any(|c| char_language(c) == Some("ja"));
+    move |c| match char_language(c) {
+        Some("zh") if has_kana => Some("ja"),
+        language => language,
+    }
+}
+
+///
+/// 检测文本的主要语种，返回字符数最多的语种，拉丁字母按每 3 个字母相当于 1 个其他文字计算
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let char_language = text_char_language(text);
+    let mut count_list: Vec<(&'static str, usize)> = Vec::new();
+    for language in text.chars().filter_map(char_language) {
+        let weight = if language == "en" { 1 } else { 3 };
+        match count_list.iter_mut().find(|(l, _)| *l == language) {
+            Some((_, count)) => *count += weight,
+            None => count_list.push((language, weight)),
+        }
+    }
+    count_list
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(language, _)| language)
+}
+
+///
+/// 按语种切分混合语言的文本，返回按顺序排列的 (语种, 文本)
+///
+/// 不区分语种的字符归入前一个片段，较短的拉丁字母片段并入相邻片段，无法判断语种时返回空列表
+pub fn split_by_language(text: &str) -> Vec<(&'static str, String)> {
+    let char_language = text_char_language(text);
+    let mut list: Vec<(Option<&'static str>, String)> = Vec::new();
+    for c in text.chars() {
+        let language = char_language(c);
+        match list.last_mut() {
+            Some((current, segment))
+                if language.is_none() || current.is_none() || *current == language =>
+            {
+                if current.is_none() {
+                    *current = language;
+                }
+                segment.push(c);
+            }
+            _ => list.push((language, c.to_string())),
+        }
+    }
+
+    let len = list.len();
+    let mut result: Vec<(&'static str, String)> = Vec::new();
+    let mut pending = String::new();
+    for (language, segment) in list {
+        // 只有整段文本都不区分语种时才会出现
+        let Some(language) = language else {
+            return Vec::new();
+        };
+        let is_short = language == "en"
+            && len > 1
+            && segment
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|i| !i.is_empty())
+                .count()
+                < LATIN_SEGMENT_MIN_WORDS;
+        match result.last_mut() {
+            Some((_, last_segment)) if is_short => last_segment.push_str(&segment),
+            None if is_short => pending.push_str(&segment),
+            Some((last, last_segment)) if *last == language => last_segment.push_str(&segment),
+            _ => {
+                let mut segment_tmp = std::mem::take(&mut pending);
+                segment_tmp.push_str(&segment);
+                result.push((language, segment_tmp));
+            }
+        }
+    }
+    result
+}
+
+///
+/// 获取语种的默认发音人
+pub fn language_default_voice(voices_list: &VoicesList, language: &str) -> Option<String> {
+    let (locale, voice) = LANGUAGE_DEFAULT_VOICE_LIST
+        .iter()
+        .find(|(l, _, _)| *l == language)
+        .map(|(_, locale, voice)| (locale.to_string(), voice.to_string()))
+        .unwrap_or_else(|| (language.to_owned(), String::new()));
+    if voices_list.voices_name_list.contains(&voice) {
+        return Some(voice);
+    }
+    let first = |list: &Vec<Arc<VoicesItem>>| list.first().map(|i| i.get_short_name());
+    voices_list
+        .by_locale_map
+        .get(&locale)
+        .and_then(first)
+        .or_else(|| {
+            let prefix = format!("{}-", language);
+            let mut locale_list: Vec<&String> = voices_list
+                .by_locale_map
+                .keys()
+                .filter(|i| i.starts_with(&prefix))
+                .collect();
+            locale_list.sort();
+            locale_list
+                .first()
+                .and_then(|i| voices_list.by_locale_map.get(*i))
+                .and_then(first)
+        })
+}
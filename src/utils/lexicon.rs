@@ -0,0 +1,221 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::TTSServerError, utils::ssml::escape_xml, AppArgs};
+
+///
+/// 微软接口支持的音标类型
+pub const LEXICON_ALPHABET_LIST: [&str; 4] = ["ipa", "sapi", "ups", "x-sampa"];
+
+///
+/// 对所有语言生效的词典 key
+const LEXICON_ALL_LOCALE: &str = "*";
+
+static GLOBAL_LEXICON: OnceCell<Lexicon> = OnceCell::new();
+
+///
+/// 发音词典条目，`phoneme` 与 `alias` 至少需要一个，同时存在时使用 `phoneme`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct LexiconEntry {
+    /// 需要替换发音的文本
+    pub grapheme: String,
+    /// 音标类型，默认为 ipa
+    #[serde(default)]
+    pub alphabet: Option<String>,
+    /// 音标
+    #[serde(default)]
+    pub phoneme: Option<String>,
+    /// 替换朗读的文本
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+impl LexiconEntry {
+    ///
+    /// 校验词典条目
+    pub fn check(&self) -> Result<(), TTSServerError> {
+        if self.grapheme.trim().is_empty() {
+            return Err(TTSServerError::ProgramError(
+                "发音词典条目 grapheme 不能为空".to_owned(),
+            ));
+        }
+        if self.phoneme.is_none() && self.alias.is_none() {
+            return Err(TTSServerError::ProgramError(format!(
+                "发音词典条目 {} 缺少 phoneme 或 alias",
+                self.grapheme
+            )));
+        }
+        if let Some(alphabet) = &self.alphabet {
+            if !LEXICON_ALPHABET_LIST.contains(&alphabet.as_str()) {
+                return Err(TTSServerError::ProgramError(format!(
+                    "发音词典条目 {} 的音标类型 {} 不支持，可用类型: {:?}",
+                    self.grapheme, alphabet, LEXICON_ALPHABET_LIST
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn to_ssml(&self, text: &str) -> String {
+        match &self.phoneme {
+            Some(phoneme) => format!(
+                "<phoneme alphabet='{}' ph='{}'>{}</phoneme>",
+                self.alphabet.as_deref().unwrap_or("ipa"),
+                escape_xml(phoneme),
+                text
+            ),
+            None => format!(
+                "<sub alias='{}'>{}</sub>",
+                escape_xml(self.alias.as_deref().unwrap_or_default()),
+                text
+            ),
+        }
+    }
+}
+
+///
+/// 服务端发音词典，按语言区分，key 可以是完整语言 `zh-CN`、语种 `zh` 或对所有语言生效的 `*`
+#[derive(Default, Debug)]
+pub struct Lexicon {
+    by_locale_map: HashMap<String, Vec<LexiconEntry>>,
+}
+
+impl Lexicon {
+    ///
+    /// 从 json 文件加载词典，格式为 `{"zh-CN": [{"grapheme": "...", "phoneme": "..."}]}`
+    pub fn from_file(path: &str) -> Result<Self, TTSServerError> {
+        let data = std::fs::read_to_string(path).map_err(|e| {
+            TTSServerError::ProgramError(format!("读取发音词典文件 {} 失败 {:?}", path, e))
+        })?;
+        let map: HashMap<String, Vec<LexiconEntry>> = serde_json::from_str(&data).map_err(|e| {
+            TTSServerError::ProgramError(format!("解析发音词典文件 {} 失败 {:?}", path, e))
+        })?;
+        for entry in map.values().flatten() {
+            entry.check()?;
+        }
+        Ok(Lexicon {
+            by_locale_map: map
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect(),
+        })
+    }
+
+    ///
+    /// 获取语言对应的词典条目，优先级为 完整语言 > 语种 > 所有语言
+    fn get_entries(&self, locale: &str) -> impl Iterator<Item = &LexiconEntry> {
+        let locale = locale.to_lowercase();
+        let language = locale.split('-').next().unwrap_or_default().to_owned();
+        [locale, language, LEXICON_ALL_LOCALE.to_owned()]
+            .into_iter()
+            .filter_map(|k| self.by_locale_map.get(&k))
+            .flatten()
+    }
+}
+
+///
+/// 加载命令行参数指定的发音词典，未指定时使用空词典
+pub fn init_lexicon() {
+    let args = AppArgs::parse_macro();
+    let lexicon = match &args.lexicon_file {
+        Some(path) => match Lexicon::from_file(path) {
+            Ok(lexicon) => {
+                info!("已加载发音词典 {}", path);
+                lexicon
+            }
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Lexicon::default(),
+    };
+    let _ = GLOBAL_LEXICON.set(lexicon);
+}
+
+///
+/// 在已转义的文本中应用发音词典，请求中的条目优先于服务端词典
+///
+/// 语言取自发音人名称，如 `zh-CN-XiaoxiaoNeural` 对应 `zh-CN`。词条按转义后的文本匹配，
+/// 以字母或数字开头 (结尾) 的词条要求前 (后) 不是字母或数字，防止替换单词的一部分，已转义的实体不会被替换
+pub fn apply_lexicon(text: &str, informant: &str, request_entries: &[LexiconEntry]) -> String {
+    let locale = informant
+        .splitn(3, '-')
+        .take(2)
+        .collect::<Vec<_>>()
+        .join("-");
+    let mut entries: Vec<&LexiconEntry> = Vec::new();
+    let global = GLOBAL_LEXICON.get();
+    for entry in request_entries
+        .iter()
+        .chain(global.into_iter().flat_map(|i| i.get_entries(&locale)))
+    {
+        if !entry.grapheme.is_empty() && !entries.iter().any(|i| i.grapheme == entry.grapheme) {
+            entries.push(entry);
+        }
+    }
+    if entries.is_empty() {
+        return text.to_owned();
+    }
+    let mut entries: Vec<(String, &LexiconEntry)> = entries
+        .into_iter()
+        .map(|i| (escape_xml(&i.grapheme), i))
+        .collect();
+    entries.sort_by_key(|(grapheme, _)| Reverse(grapheme.len()));
+
+    let mut result = String::with_capacity(text.len());
+    let mut index = 0;
+    'outer: while index < text.len() {
+        let rest = &text[index..];
+        if let Some(len) = entity_len(rest) {
+            result.push_str(&rest[..len]);
+            index += len;
+            continue;
+        }
+        let prev = text[..index].chars().next_back();
+        for (grapheme, entry) in entries.iter() {
+            let grapheme = grapheme.as_str();
+            if !rest.starts_with(grapheme) {
+                continue;
+            }
+            let next = rest[grapheme.len()..].chars().next();
+            let is_word = |c: Option<char>| c.map(|c| c.is_ascii_alphanumeric()).unwrap_or(false);
+            if (is_word(grapheme.chars().next()) && is_word(prev))
+                || (is_word(grapheme.chars().next_back()) && is_word(next))
+            {
+                continue;
+            }
+            result.push_str(&entry.to_ssml(grapheme));
+            index += grapheme.len();
+            continue 'outer;
+        }
+        let c = rest.chars().next().unwrap();
+        result.push(c);
+        index += c.len_utf8();
+    }
+    result
+}
+
+///
+/// 文本开头为已转义实体时返回实体长度
+fn entity_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('&')?;
+    let end = rest.find(';')?;
+    let name = &rest[..end];
+    let valid = !name.is_empty()
+        && match name.strip_prefix('#') {
+            Some(num) => num
+                .strip_prefix('x')
+                .map(|i| !i.is_empty() && i.chars().all(|c| c.is_ascii_hexdigit()))
+                .unwrap_or_else(|| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit())),
+            None => name.chars().all(|c| c.is_ascii_alphabetic()),
+        };
+    if valid {
+        Some(end + 2)
+    } else {
+        None
+    }
+}
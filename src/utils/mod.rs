@@ -1,7 +1,20 @@
+pub(crate) mod audio;
 pub(crate) mod azure_api;
+pub(crate) mod language;
+pub(crate) mod lexicon;
+pub(crate) mod quota;
 pub mod log;
+pub(crate) mod ssml;
+pub(crate) mod subtitle;
+pub(crate) mod text;
+pub(crate) mod text_rule;
+pub(crate) mod voice_cache;
+pub(crate) mod voice_preset;
+
+use std::time::{Duration, SystemTime};
 
 use rand::Rng;
+use serde::{Deserialize, Deserializer};
 
 /// 生成随机字符
 ///
@@ -42,3 +55,48 @@ pub fn binary_search(bin: &[u8], search: &[u8]) -> Option<usize> {
     }
     None
 }
+
+///
+/// 定时检查文件的修改时间，文件修改后调用 `on_change`，用于配置文件的自动重新加载
+pub fn watch_file_modified<F>(path: String, interval: Duration, on_change: F)
+where
+    F: Fn(&str) + Send + 'static,
+{
+    let modified_time = |path: &str| {
+        std::fs::metadata(path)
+            .and_then(|i| i.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    };
+    let mut last_modified = modified_time(&path);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let modified = modified_time(&path);
+            if modified != last_modified {
+                last_modified = modified;
+                on_change(&path);
+            }
+        }
+    });
+}
+
+///
+/// 兼容数字与字符串两种写法的参数，统一转为字符串
+pub fn deserialize_number_or_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        String(String),
+    }
+    Ok(
+        Option::<NumberOrString>::deserialize(deserializer)?.map(|i| match i {
+            NumberOrString::Number(n) => n.to_string(),
+            NumberOrString::String(s) => s,
+        }),
+    )
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::Utc;
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::AppArgs;
+
+///
+/// 字符用量写入文件的间隔
+const SUBSCRIBE_USAGE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 订阅 key 的字符用量，key 为 [AzureSubscribeKey::usage_id](crate::utils::azure_api::AzureSubscribeKey::usage_id)
+static GLOBAL_SUBSCRIBE_USAGE: Lazy<Mutex<HashMap<String, SubscribeKeyUsage>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 用量是否有未保存的修改
+static SUBSCRIBE_USAGE_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// 写入用量文件的锁，防止定时保存与退出时保存同时写入
+static SUBSCRIBE_USAGE_SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+///
+/// 订阅 key 当月已使用的字符数，按 UTC 自然月统计
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct SubscribeKeyUsage {
+    /// 统计的月份，如 2023-01
+    pub month: String,
+    /// 已发送的字符数
+    pub chars: u64,
+}
+
+impl SubscribeKeyUsage {
+    ///
+    /// 指定月份已使用的字符数，记录的不是该月份时为 0
+    pub fn used_in(&self, month: &str) -> u64 {
+        if self.month == month {
+            self.chars
+        } else {
+            0
+        }
+    }
+
+    ///
+    /// 记录指定月份使用的字符数，进入新的月份时重新计数
+    pub fn add(&mut self, month: &str, chars: u64) {
+        if self.month != month {
+            self.month = month.to_owned();
+            self.chars = 0;
+        }
+        self.chars += chars;
+    }
+
+    ///
+    /// 退回指定月份预留的字符数，已进入新的月份时不处理
+    pub fn refund(&mut self, month: &str, chars: u64) {
+        if self.month == month {
+            self.chars = self.chars.saturating_sub(chars);
+        }
+    }
+
+    ///
+    /// 剩余额度是否足够发送指定字符数，额度为 0 时不限制
+    pub fn has_budget(&self, month: &str, budget: u64, chars: u64) -> bool {
+        budget == 0 || self.used_in(month) + chars <= budget
+    }
+}
+
+///
+/// 当前月份，如 2023-01
+pub fn current_month() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+fn save_subscribe_usage(path: &str) {
+    let _lock = SUBSCRIBE_USAGE_SAVE_LOCK.lock().unwrap();
+    let data = {
+        let map = GLOBAL_SUBSCRIBE_USAGE.lock().unwrap();
+        serde_json::to_vec(&*map)
+    };
+    let write = |data: Vec<u8>| -> std::io::Result<()> {
+        let path = std::path::Path::new(path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, path)
+    };
+    let result = data
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|data| write(data).map_err(|e| format!("{:?}", e)));
+    if let Err(e) = result {
+        // 保存失败时保留修改标记，下次继续尝试
+        SUBSCRIBE_USAGE_DIRTY.store(true, Ordering::Release);
+        warn!("保存订阅key字符用量 {} 失败 {}", path, e);
+    }
+}
+
+///
+/// 加载 `--subscribe-usage-file` 中记录的字符用量，并定时保存用量
+pub fn init_subscribe_usage() {
+    let args = AppArgs::parse_macro();
+    let path = args.subscribe_usage_file.clone();
+    match std::fs::read(&path) {
+        Ok(data) => match serde_json::from_slice::<HashMap<String, SubscribeKeyUsage>>(&data) {
+            Ok(map) => {
+                info!("已加载 {} 个订阅key的字符用量 {}", map.len(), path);
+                *GLOBAL_SUBSCRIBE_USAGE.lock().unwrap() = map;
+            }
+            Err(e) => error!("解析订阅key字符用量 {} 失败，重新计数 {:?}", path, e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => error!("读取订阅key字符用量 {} 失败，重新计数 {:?}", path, e),
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SUBSCRIBE_USAGE_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if SUBSCRIBE_USAGE_DIRTY.swap(false, Ordering::AcqRel) {
+                let path = path.clone();
+                let _ = tokio::task::spawn_blocking(move || save_subscribe_usage(&path)).await;
+            }
+        }
+    });
+}
+
+///
+/// 立即保存未保存的字符用量，程序退出前调用
+pub fn flush_subscribe_usage() {
+    if SUBSCRIBE_USAGE_DIRTY.swap(false, Ordering::AcqRel) {
+        let args = AppArgs::parse_macro();
+        save_subscribe_usage(&args.subscribe_usage_file);
+    }
+}
+
+///
+/// 当月剩余额度足够时预留 `chars` 个字符并返回 true，检查与记录在同一个锁内完成，防止并发请求超出额度。
+/// 额度为 0 时不限制
+pub fn try_reserve_subscribe_usage(id: &str, budget: u64, chars: u64) -> bool {
+    let month = current_month();
+    let mut map = GLOBAL_SUBSCRIBE_USAGE.lock().unwrap();
+    let usage = map.entry(id.to_owned()).or_default();
+    if !usage.has_budget(&month, budget, chars) {
+        return false;
+    }
+    usage.add(&month, chars);
+    SUBSCRIBE_USAGE_DIRTY.store(true, Ordering::Release);
+    true
+}
+
+///
+/// 退回预留的字符数，请求未能发送时调用
+pub fn refund_subscribe_usage(id: &str, chars: u64) {
+    let month = current_month();
+    if let Some(usage) = GLOBAL_SUBSCRIBE_USAGE.lock().unwrap().get_mut(id) {
+        usage.refund(&month, chars);
+        SUBSCRIBE_USAGE_DIRTY.store(true, Ordering::Release);
+    }
+}
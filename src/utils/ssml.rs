@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+use quick_xml::{events::Event, Reader};
+
+use crate::error::TTSServerError;
+
+///
+/// 校验 SSML 文档，返回文档中使用的发音人 (按出现顺序去重)
+///
+/// 要求文档为格式正确的 XML，根元素为 `speak`，至少包含一个 `voice` 元素，
+/// 且所有 `voice` 元素的 `name` 都在接口的发音人列表中
+pub fn validate_ssml(
+    ssml: &str,
+    voices_name_list: &HashSet<String>,
+) -> Result<Vec<String>, TTSServerError> {
+    let invalid = |msg: String| TTSServerError::ProgramError(format!("SSML 校验失败: {}", msg));
+
+    let mut reader = Reader::from_str(ssml);
+    let mut depth = 0usize;
+    let mut has_root = false;
+    let mut voices: Vec<String> = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|e| {
+            invalid(format!(
+                "第 {} 字节附近 XML 格式错误 {}",
+                reader.buffer_position(),
+                e
+            ))
+        })?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if depth == 0 {
+                    if has_root {
+                        return Err(invalid("存在多个根元素".to_owned()));
+                    }
+                    if name != "speak" {
+                        return Err(invalid(format!("根元素必须为 speak, 当前为 {}", name)));
+                    }
+                    has_root = true;
+                }
+                if name == "voice" {
+                    let voice = e
+                        .try_get_attribute("name")
+                        .map_err(|e| invalid(format!("voice 属性错误 {}", e)))?
+                        .ok_or_else(|| invalid("voice 元素缺少 name 属性".to_owned()))?
+                        .unescape_value()
+                        .map_err(|e| invalid(format!("voice 属性错误 {}", e)))?
+                        .trim()
+                        .to_owned();
+                    if !voices_name_list.contains(&voice) {
+                        return Err(invalid(format!("发音人 {} 不存在", voice)));
+                    }
+                    if !voices.contains(&voice) {
+                        voices.push(voice);
+                    }
+                }
+                if let Event::Start(_) = event {
+                    depth += 1;
+                }
+            }
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+            }
+            Event::Text(ref e) if depth == 0 && !e.iter().all(|c| c.is_ascii_whitespace()) => {
+                return Err(invalid("根元素之外存在文本".to_owned()));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !has_root {
+        return Err(invalid("缺少 speak 根元素".to_owned()));
+    }
+    if depth != 0 {
+        return Err(invalid("存在未闭合的元素".to_owned()));
+    }
+    if voices.is_empty() {
+        return Err(invalid("至少需要一个 voice 元素".to_owned()));
+    }
+    Ok(voices)
+}
+
+///
+/// 转义 XML 文本及属性值中的特殊字符，并移除控制字符
+///
+/// 保留制表符与换行符，移除 XML 1.0 中不允许出现的其他 C0 控制字符及 `U+FFFE`、`U+FFFF`。
+/// DEL 及 C1 控制字符虽然允许出现，但 XML 1.0 不建议使用，同样会被移除
+pub fn escape_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '\'' => result.push_str("&apos;"),
+            '"' => result.push_str("&quot;"),
+            '\t' | '\n' | '\r' => result.push(c),
+            '\u{FFFE}' | '\u{FFFF}' => {}
+            c if c.is_control() => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// SSML prosody 音调预设等级
+pub const SSML_PITCH_LEVELS: [&str; 6] = ["x-low", "low", "medium", "high", "x-high", "default"];
+
+/// SSML prosody 音量预设等级
+pub const SSML_VOLUME_LEVELS: [&str; 7] = [
+    "silent", "x-soft", "soft", "medium", "loud", "x-loud", "default",
+];
+
+fn parse_number(value: &str) -> Option<f32> {
+    value.trim().parse::<f32>().ok().filter(|i| i.is_finite())
+}
+
+///
+/// 解析音调参数，返回 prosody 元素 pitch 属性的值
+///
+/// 支持倍数 `0` - `2` (1 为原始音调)、相对值 `+10%`、赫兹 `200Hz` / `-20Hz`、
+/// 半音 `+2st` 以及预设等级 `x-low` - `x-high`，无法解析时返回 None
+pub fn parse_prosody_pitch(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    if SSML_PITCH_LEVELS.contains(&value.as_str()) {
+        return Some(value);
+    }
+    if let Some(num) = value.strip_suffix("hz") {
+        let signed = num.trim_start().starts_with(['+', '-']);
+        let num = parse_number(num)?;
+        return if signed {
+            Some(format!("{:+}Hz", num))
+        } else if num > 0.0 {
+            Some(format!("{}Hz", num))
+        } else {
+            None
+        };
+    }
+    if let Some(num) = value.strip_suffix("st") {
+        return Some(format!("{:+}st", parse_number(num)?));
+    }
+    if let Some(num) = value.strip_suffix('%') {
+        return Some(format!("{:+}%", parse_number(num)?));
+    }
+    let pitch = parse_number(&value)?.clamp(0.0, 2.0);
+    Some(format!("{:+.0}%", 50.00 * pitch - 50.00))
+}
+
+///
+/// 解析音量参数，返回 prosody 元素 volume 属性的值
+///
+/// 支持倍数 `0` - `2` (1 为原始音量)、相对值 `+10%` 以及预设等级 `silent` - `x-loud`，无法解析时返回 None
+pub fn parse_prosody_volume(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    if SSML_VOLUME_LEVELS.contains(&value.as_str()) {
+        return Some(value);
+    }
+    if let Some(num) = value.strip_suffix('%') {
+        return Some(format!("{:+}%", parse_number(num)?.max(-100.0)));
+    }
+    let volume = parse_number(&value)?.clamp(0.0, 2.0);
+    Some(format!("{:+.0}%", 100.00 * volume - 100.00))
+}
+
+/// 音调曲线中的单个 `(位置%,音调)`
+static PROSODY_CONTOUR_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\(\s*(\d+(?:\.\d+)?)%\s*,\s*([+-]?\d+(?:\.\d+)?(?:Hz|st|%))\s*\)").unwrap()
+});
+
+///
+/// 校验音调曲线参数，返回 prosody 元素 contour 属性的值
+///
+/// 格式为一组 `(位置%,音调)`，位置范围 0 - 100，音调支持 `Hz`、`st`、`%`，如 `(0%,+20Hz) (50%,-2st)`
+pub fn parse_prosody_contour(value: &str) -> Option<String> {
+    let mut list = Vec::new();
+    for cap in PROSODY_CONTOUR_REGEX.captures_iter(value) {
+        let cap = cap.ok()?;
+        let position = parse_number(&cap[1])?;
+        if position > 100.0 {
+            return None;
+        }
+        list.push(format!("({}%,{})", &cap[1], &cap[2]));
+    }
+    if list.is_empty()
+        || !PROSODY_CONTOUR_REGEX
+            .replace_all(value, "")
+            .trim()
+            .is_empty()
+    {
+        return None;
+    }
+    Some(list.join(" "))
+}
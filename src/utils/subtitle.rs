@@ -0,0 +1,180 @@
+use std::fmt::Write;
+
+use crate::{
+    error::TTSServerError,
+    ms_tts::{MsTtsBoundary, MsTtsBoundaryType},
+};
+
+/// 使用单词边界拼接字幕时，每条字幕的最大字符数
+const MAX_CUE_CHARS: usize = 32;
+
+/// 单词间隔超过该时长时另起一条字幕 (100 纳秒)
+const MAX_CUE_GAP: u64 = 8_000_000;
+
+///
+/// 字幕格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+    Lrc,
+}
+
+impl TryFrom<&str> for SubtitleFormat {
+    type Error = TTSServerError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "srt" => Ok(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Ok(SubtitleFormat::WebVtt),
+            "lrc" => Ok(SubtitleFormat::Lrc),
+            _ => Err(TTSServerError::ProgramError(format!(
+                "不支持的字幕格式: {}, 可用格式: srt, vtt, lrc",
+                value
+            ))),
+        }
+    }
+}
+
+impl SubtitleFormat {
+    /// 字幕文件类型
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "application/x-subrip",
+            SubtitleFormat::WebVtt => "text/vtt",
+            SubtitleFormat::Lrc => "text/plain",
+        }
+    }
+}
+
+///
+/// 一条字幕
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleCue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+///
+/// 根据边界事件生成字幕条目
+///
+/// 存在句子边界事件时每句一条字幕，否则将单词边界事件按长度及停顿合并为字幕
+pub fn boundaries_to_cues(boundaries: &[MsTtsBoundary]) -> Vec<SubtitleCue> {
+    let sentence_list = boundaries
+        .iter()
+        .filter(|i| i.boundary_type == MsTtsBoundaryType::Sentence)
+        .collect::<Vec<_>>();
+    if !sentence_list.is_empty() {
+        return sentence_list
+            .into_iter()
+            .map(|i| SubtitleCue {
+                start: i.offset,
+                end: i.offset + i.duration,
+                text: i.text.trim().to_owned(),
+            })
+            .collect();
+    }
+
+    let mut cues: Vec<SubtitleCue> = Vec::new();
+    for word in boundaries
+        .iter()
+        .filter(|i| i.boundary_type == MsTtsBoundaryType::Word)
+    {
+        let text = word.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let end = word.offset + word.duration;
+        match cues.last_mut() {
+            Some(cue)
+                if cue.text.chars().count() + text.chars().count() <= MAX_CUE_CHARS
+                    && word.offset.saturating_sub(cue.end) <= MAX_CUE_GAP =>
+            {
+                if need_space(&cue.text, text) {
+                    cue.text.push(' ');
+                }
+                cue.text.push_str(text);
+                cue.end = end;
+            }
+            _ => cues.push(SubtitleCue {
+                start: word.offset,
+                end,
+                text: text.to_owned(),
+            }),
+        }
+    }
+    cues
+}
+
+///
+/// 拼接单词时两个单词之间是否需要空格 (中日韩等文字不需要空格)
+fn need_space(left: &str, right: &str) -> bool {
+    let is_spaced =
+        |c: char| c.is_ascii_alphanumeric() || (c.is_alphabetic() && (c as u32) < 0x2E80);
+    match (left.chars().last(), right.chars().next()) {
+        (Some(l), Some(r)) => is_spaced(l) && is_spaced(r),
+        _ => false,
+    }
+}
+
+///
+/// 生成字幕文件内容
+pub fn generate_subtitle(format: SubtitleFormat, boundaries: &[MsTtsBoundary]) -> String {
+    let cues = boundaries_to_cues(boundaries);
+    let mut result = String::new();
+    match format {
+        SubtitleFormat::Srt => {
+            for (index, cue) in cues.iter().enumerate() {
+                let _ = write!(
+                    result,
+                    "{}\n{} --> {}\n{}\n\n",
+                    index + 1,
+                    format_time(cue.start, ','),
+                    format_time(cue.end, ','),
+                    cue.text
+                );
+            }
+        }
+        SubtitleFormat::WebVtt => {
+            result.push_str("WEBVTT\n\n");
+            for cue in cues.iter() {
+                let _ = write!(
+                    result,
+                    "{} --> {}\n{}\n\n",
+                    format_time(cue.start, '.'),
+                    format_time(cue.end, '.'),
+                    cue.text
+                );
+            }
+        }
+        SubtitleFormat::Lrc => {
+            for cue in cues.iter() {
+                let centis = cue.start / 100_000;
+                let _ = writeln!(
+                    result,
+                    "[{:02}:{:02}.{:02}]{}",
+                    centis / 6000,
+                    centis / 100 % 60,
+                    centis % 100,
+                    cue.text
+                );
+            }
+        }
+    }
+    result
+}
+
+///
+/// 格式化时间 HH:MM:SS,mmm
+fn format_time(ticks: u64, separator: char) -> String {
+    let millis = ticks / 10_000;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
@@ -0,0 +1,77 @@
+///
+/// 段落分隔符
+const PARAGRAPH_DELIMITER: [char; 2] = ['\n', '\r'];
+
+///
+/// 句末标点 (全角标点后无需空格，半角标点需后跟空白才视为句末，防止切开小数等内容)
+const SENTENCE_DELIMITER_FULL: [char; 5] = ['。', '！', '？', '；', '…'];
+const SENTENCE_DELIMITER_HALF: [char; 4] = ['.', '!', '?', ';'];
+
+///
+/// 句中停顿标点
+const CLAUSE_DELIMITER: [char; 7] = ['，', '、', '：', ',', ':', ' ', '\t'];
+
+///
+/// 长文本切分
+///
+/// 按字符数切分文本，每段不超过 `max_len` 个字符。
+/// 优先在段落处切分，其次是句末标点，再次是句中停顿标点，都不存在时才按长度硬切
+///
+/// Examples
+///
+/// ```
+/// let list = split_text("第一句。第二句。", 5);
+/// assert_eq!(list, vec!["第一句。", "第二句。"]);
+/// ```
+pub fn split_text(text: &str, max_len: usize) -> Vec<String> {
+    let max_len = max_len.max(1);
+    let mut list = Vec::new();
+    let mut rest: Vec<char> = text.chars().collect();
+
+    while rest.len() > max_len {
+        let index = find_split_index(&rest[..max_len], rest.get(max_len).copied());
+        let chunk: String = rest.drain(..index).collect();
+        let chunk = chunk.trim();
+        if !chunk.is_empty() {
+            list.push(chunk.to_owned());
+        }
+    }
+    let chunk: String = rest.into_iter().collect();
+    let chunk = chunk.trim();
+    if !chunk.is_empty() {
+        list.push(chunk.to_owned());
+    }
+    list
+}
+
+///
+/// 在窗口内寻找切分位置，返回值为切分后第一段的字符数
+fn find_split_index(window: &[char], next: Option<char>) -> usize {
+    let len = window.len();
+    let char_at = |i: usize| window.get(i).copied().or(next);
+
+    let is_paragraph = |i: usize| PARAGRAPH_DELIMITER.contains(&window[i]);
+    let is_sentence = |i: usize| {
+        let c = window[i];
+        SENTENCE_DELIMITER_FULL.contains(&c)
+            || (SENTENCE_DELIMITER_HALF.contains(&c)
+                && char_at(i + 1).map(|n| n.is_whitespace()).unwrap_or(true))
+    };
+    let is_clause = |i: usize| CLAUSE_DELIMITER.contains(&window[i]);
+
+    let levels: [&dyn Fn(usize) -> bool; 3] = [&is_paragraph, &is_sentence, &is_clause];
+
+    // 优先选择位于窗口后半段的高优先级分隔符，避免切出过短的片段
+    for level in levels.iter() {
+        if let Some(i) = (len / 2..len).rev().find(|&i| level(i)) {
+            return i + 1;
+        }
+    }
+    for level in levels.iter() {
+        if let Some(i) = (0..len / 2).rev().find(|&i| level(i)) {
+            return i + 1;
+        }
+    }
+
+    len
+}
@@ -0,0 +1,267 @@
+use std::{
+    ops::Range,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use fancy_regex::Regex;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::{error::TTSServerError, utils::watch_file_modified, AppArgs};
+
+///
+/// 检查规则文件是否修改的间隔
+const TEXT_RULE_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+///
+/// 内置规则，将全角标点替换为半角标点加空格
+const DEFAULT_TEXT_RULE_LIST: [(&str, &str); 6] = [
+    ("？", "? "),
+    ("，", ", "),
+    ("。", ". "),
+    ("：", ": "),
+    ("；", "; "),
+    ("！", "! "),
+];
+
+static GLOBAL_TEXT_RULES: Lazy<RwLock<Arc<TextRuleSet>>> = Lazy::new(|| {
+    let rules = TextRuleSet::compile(TextRuleConfig {
+        default_rules: true,
+        rules: Vec::new(),
+    })
+    .unwrap();
+    RwLock::new(Arc::new(rules))
+});
+
+///
+/// 文本规则配置文件
+#[derive(Deserialize, Debug)]
+pub struct TextRuleConfig {
+    /// 是否在自定义规则之后继续使用内置的标点替换规则
+    #[serde(default = "default_true")]
+    pub default_rules: bool,
+    /// 按顺序执行的规则
+    #[serde(default)]
+    pub rules: Vec<TextRule>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+///
+/// 文本规则，`locale` 为空时对所有语言生效，可填写完整语言 `zh-CN` 或语种 `zh`
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TextRule {
+    /// 正则替换，替换文本中可使用 `$1` 引用分组
+    Regex {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+        #[serde(default)]
+        locale: Vec<String>,
+    },
+    /// 普通文本替换
+    Literal {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+        #[serde(default)]
+        locale: Vec<String>,
+    },
+    /// 匹配的内容不会被其他规则修改
+    Skip {
+        pattern: String,
+        #[serde(default)]
+        locale: Vec<String>,
+    },
+}
+
+enum TextRuleKind {
+    Regex(Regex, String),
+    Literal(String, String),
+    Skip(Regex),
+}
+
+struct CompiledTextRule {
+    kind: TextRuleKind,
+    locale: Vec<String>,
+}
+
+impl CompiledTextRule {
+    fn is_match_locale(&self, locale: &str) -> bool {
+        if self.locale.is_empty() {
+            return true;
+        }
+        let locale = locale.to_lowercase();
+        let language = locale.split('-').next().unwrap_or_default();
+        self.locale.iter().any(|i| i == &locale || i == language)
+    }
+}
+
+///
+/// 编译后的文本规则
+pub struct TextRuleSet {
+    rules: Vec<CompiledTextRule>,
+}
+
+impl TextRuleSet {
+    ///
+    /// 编译规则，正则错误时返回出错的规则
+    pub fn compile(config: TextRuleConfig) -> Result<Self, TTSServerError> {
+        let compile_regex = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| {
+                TTSServerError::ProgramError(format!("文本规则正则 {} 错误 {:?}", pattern, e))
+            })
+        };
+        let mut rules = Vec::with_capacity(config.rules.len() + DEFAULT_TEXT_RULE_LIST.len());
+        for rule in config.rules {
+            let (kind, locale) = match rule {
+                TextRule::Regex {
+                    pattern,
+                    replacement,
+                    locale,
+                } => (
+                    TextRuleKind::Regex(compile_regex(&pattern)?, replacement),
+                    locale,
+                ),
+                TextRule::Literal {
+                    pattern,
+                    replacement,
+                    locale,
+                } => {
+                    if pattern.is_empty() {
+                        return Err(TTSServerError::ProgramError(
+                            "文本规则 literal 的 pattern 不能为空".to_owned(),
+                        ));
+                    }
+                    (TextRuleKind::Literal(pattern, replacement), locale)
+                }
+                TextRule::Skip { pattern, locale } => {
+                    (TextRuleKind::Skip(compile_regex(&pattern)?), locale)
+                }
+            };
+            rules.push(CompiledTextRule {
+                kind,
+                locale: locale.iter().map(|i| i.to_lowercase()).collect(),
+            });
+        }
+        if config.default_rules {
+            rules.extend(
+                DEFAULT_TEXT_RULE_LIST
+                    .iter()
+                    .map(|(k, v)| CompiledTextRule {
+                        kind: TextRuleKind::Literal(k.to_string(), v.to_string()),
+                        locale: Vec::new(),
+                    }),
+            );
+        }
+        Ok(TextRuleSet { rules })
+    }
+
+    ///
+    /// 从 json 文件加载规则
+    pub fn from_file(path: &str) -> Result<Self, TTSServerError> {
+        let data = std::fs::read_to_string(path).map_err(|e| {
+            TTSServerError::ProgramError(format!("读取文本规则文件 {} 失败 {:?}", path, e))
+        })?;
+        let config: TextRuleConfig = serde_json::from_str(&data).map_err(|e| {
+            TTSServerError::ProgramError(format!("解析文本规则文件 {} 失败 {:?}", path, e))
+        })?;
+        Self::compile(config)
+    }
+
+    ///
+    /// 按顺序执行对应语言的规则，跳过规则匹配的内容保持原样
+    pub fn apply(&self, text: &str, locale: &str) -> String {
+        let rules: Vec<&CompiledTextRule> = self
+            .rules
+            .iter()
+            .filter(|i| i.is_match_locale(locale))
+            .collect();
+
+        let mut skip_list: Vec<Range<usize>> = Vec::new();
+        for rule in rules.iter() {
+            if let TextRuleKind::Skip(re) = &rule.kind {
+                skip_list.extend(re.find_iter(text).flatten().map(|m| m.start()..m.end()));
+            }
+        }
+        skip_list.sort_by_key(|i| i.start);
+
+        let mut result = String::with_capacity(text.len());
+        let mut index = 0;
+        for range in skip_list {
+            if range.start < index {
+                // 与前一个跳过区域重叠
+                if range.end > index {
+                    result.push_str(&text[index..range.end]);
+                    index = range.end;
+                }
+                continue;
+            }
+            result.push_str(&Self::replace(&rules, &text[index..range.start]));
+            result.push_str(&text[range.clone()]);
+            index = range.end;
+        }
+        result.push_str(&Self::replace(&rules, &text[index..]));
+        result
+    }
+
+    fn replace(rules: &[&CompiledTextRule], text: &str) -> String {
+        let mut text = text.to_owned();
+        for rule in rules {
+            match &rule.kind {
+                TextRuleKind::Regex(re, replacement) => {
+                    text = re.replace_all(&text, replacement.as_str()).to_string();
+                }
+                TextRuleKind::Literal(pattern, replacement) => {
+                    text = text.replace(pattern.as_str(), replacement);
+                }
+                TextRuleKind::Skip(_) => {}
+            }
+        }
+        text
+    }
+}
+
+///
+/// 加载命令行参数指定的文本规则，未指定时使用内置规则。文件修改后会自动重新加载
+pub fn init_text_rule() {
+    let args = AppArgs::parse_macro();
+    let path = match &args.text_rule_file {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    match TextRuleSet::from_file(&path) {
+        Ok(rules) => {
+            info!("已加载文本规则 {}", path);
+            *GLOBAL_TEXT_RULES.write().unwrap() = Arc::new(rules);
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    watch_file_modified(
+        path,
+        TEXT_RULE_RELOAD_INTERVAL,
+        |path| match TextRuleSet::from_file(path) {
+            Ok(rules) => {
+                info!("文本规则文件已修改，重新加载 {}", path);
+                *GLOBAL_TEXT_RULES.write().unwrap() = Arc::new(rules);
+            }
+            Err(e) => error!("重新加载文本规则失败，继续使用原有规则 {}", e),
+        },
+    );
+}
+
+///
+/// 使用当前的文本规则处理文本
+pub fn normalize_text(text: &str, locale: &str) -> String {
+    let rules = GLOBAL_TEXT_RULES.read().unwrap().clone();
+    rules.apply(text, locale)
+}
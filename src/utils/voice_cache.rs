@@ -0,0 +1,167 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{TimeZone, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::TTSServerError,
+    utils::azure_api::{AzureApiEdgeFree, AzureApiSubscribeToken, VoicesItem},
+    AppArgs,
+};
+
+///
+/// 发音人列表缓存文件内容
+#[derive(Serialize, Deserialize, Debug)]
+struct VoicesCacheFile<T> {
+    /// 获取发音人列表的时间戳，单位为秒
+    update_time: i64,
+    voices: Vec<T>,
+}
+
+///
+/// 缓存文件路径，每个接口及地域对应一个文件，如 `edge.json`、`subscribe-eastasia.json`
+fn voices_cache_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+///
+/// `--voices-cache-dir` 指定的缓存目录
+pub fn voices_cache_dir() -> PathBuf {
+    PathBuf::from(&AppArgs::parse_macro().voices_cache_dir)
+}
+
+///
+/// 读取缓存的发音人列表，缓存不存在或无法解析时返回 None
+pub async fn load_voices_cache(dir: &Path, name: &str) -> Option<Vec<Arc<VoicesItem>>> {
+    let path = voices_cache_path(dir, name);
+    let data = tokio::fs::read(&path).await.ok()?;
+    match serde_json::from_slice::<VoicesCacheFile<VoicesItem>>(&data) {
+        Ok(cache) => {
+            info!(
+                "使用本地缓存的发音人列表 {}，更新时间 {}",
+                path.display(),
+                Utc.timestamp_opt(cache.update_time, 0)
+                    .single()
+                    .map(|i| i.to_string())
+                    .unwrap_or_default()
+            );
+            Some(cache.voices.into_iter().map(Arc::new).collect())
+        }
+        Err(e) => {
+            warn!("解析发音人列表缓存 {} 失败 {:?}", path.display(), e);
+            None
+        }
+    }
+}
+
+///
+/// 保存发音人列表到缓存目录，先写入临时文件再替换，防止读取到不完整的文件
+pub async fn save_voices_cache(dir: &Path, name: &str, list: &[Arc<VoicesItem>]) {
+    let path = voices_cache_path(dir, name);
+    let cache = VoicesCacheFile {
+        update_time: Utc::now().timestamp(),
+        voices: list.iter().map(|i| i.as_ref()).collect(),
+    };
+    let write = |data: Vec<u8>| async {
+        tokio::fs::create_dir_all(dir).await?;
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await
+    };
+    let result = match serde_json::to_vec(&cache) {
+        Ok(data) => write(data).await.map_err(|e| format!("{:?}", e)),
+        Err(e) => Err(format!("{:?}", e)),
+    };
+    if let Err(e) = result {
+        warn!("保存发音人列表缓存 {} 失败 {}", path.display(), e);
+    }
+}
+
+///
+/// 获取发音人列表
+///
+/// 指定了 `--do-not-update-speakers-list` 时不请求接口，直接使用本地缓存；否则请求接口并更新缓存，
+/// 请求失败时使用本地缓存。没有缓存时使用程序内置的发音人列表
+pub async fn load_voices_list<F>(name: &str, request: F, builtin: &[u8]) -> Vec<Arc<VoicesItem>>
+where
+    F: Future<Output = Result<Vec<Arc<VoicesItem>>, TTSServerError>>,
+{
+    let args = AppArgs::parse_macro();
+    load_voices_list_from(
+        &voices_cache_dir(),
+        args.do_not_update_speakers_list,
+        name,
+        request,
+        builtin,
+    )
+    .await
+}
+
+///
+/// 使用指定的缓存目录获取发音人列表，`do_not_update` 对应 `--do-not-update-speakers-list`
+pub(crate) async fn load_voices_list_from<F>(
+    dir: &Path,
+    do_not_update: bool,
+    name: &str,
+    request: F,
+    builtin: &[u8],
+) -> Vec<Arc<VoicesItem>>
+where
+    F: Future<Output = Result<Vec<Arc<VoicesItem>>, TTSServerError>>,
+{
+    if do_not_update {
+        if let Some(list) = load_voices_cache(dir, name).await {
+            return list;
+        }
+    } else {
+        match request.await {
+            Ok(list) => {
+                save_voices_cache(dir, name, &list).await;
+                return list;
+            }
+            Err(e) => {
+                warn!("请求 {} 发音人列表出错，改用缓存数据！{:?}", name, e);
+                if let Some(list) = load_voices_cache(dir, name).await {
+                    return list;
+                }
+            }
+        }
+    }
+    info!("使用程序内置的 {} 发音人列表", name);
+    let list: Vec<VoicesItem> = serde_json::from_slice(builtin).unwrap();
+    list.into_iter().map(Arc::new).collect()
+}
+
+///
+/// 按 `--voices-refresh-interval` 定时刷新发音人列表，指定了 `--do-not-update-speakers-list` 时不刷新
+pub fn init_voices_list_refresh() {
+    let args = AppArgs::parse_macro();
+    if args.do_not_update_speakers_list || args.voices_refresh_interval == 0 {
+        return;
+    }
+    let period = Duration::from_secs(args.voices_refresh_interval * 60 * 60);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            if !args.close_edge_free_api {
+                match AzureApiEdgeFree::new().refresh_vices_list().await {
+                    Ok(_) => info!("已刷新 edge 接口发音人列表"),
+                    Err(e) => error!("刷新 edge 接口发音人列表失败，继续使用原有列表 {:?}", e),
+                }
+            }
+            if !args.close_official_subscribe_api {
+                match AzureApiSubscribeToken::refresh_vices_mixed_list().await {
+                    Ok(_) => info!("已刷新订阅接口发音人列表"),
+                    Err(e) => error!("刷新订阅接口发音人列表失败，继续使用原有列表 {:?}", e),
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::TTSServerError,
+    utils::{deserialize_number_or_string, watch_file_modified},
+    AppArgs,
+};
+
+///
+/// 检查预设文件是否修改的间隔
+const VOICE_PRESET_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+static GLOBAL_VOICE_PRESETS: Lazy<RwLock<Arc<BTreeMap<String, VoicePreset>>>> =
+    Lazy::new(|| RwLock::new(Arc::new(BTreeMap::new())));
+
+///
+/// 发音人预设，请求中未填写的参数使用预设中的值
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct VoicePreset {
+    /// 预设说明
+    #[serde(default)]
+    pub desc: String,
+    /// 发音人
+    pub informant: Option<String>,
+    /// 音频风格
+    pub style: Option<String>,
+    /// 角色扮演
+    pub role: Option<String>,
+    /// 风格强度
+    pub style_degree: Option<f32>,
+    /// 语速
+    pub rate: Option<f32>,
+    /// 音调
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
+    pub pitch: Option<String>,
+    /// 音量
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
+    pub volume: Option<String>,
+    /// 音频格式
+    pub quality: Option<String>,
+}
+
+///
+/// 从 json 文件加载预设，格式为 `{"narrator-zh": {"informant": "zh-CN-YunxiNeural", "style": "narration-relaxed"}}`
+fn load_voice_preset(path: &str) -> Result<BTreeMap<String, VoicePreset>, TTSServerError> {
+    let data = std::fs::read_to_string(path).map_err(|e| {
+        TTSServerError::ProgramError(format!("读取发音人预设文件 {} 失败 {:?}", path, e))
+    })?;
+    serde_json::from_str(&data).map_err(|e| {
+        TTSServerError::ProgramError(format!("解析发音人预设文件 {} 失败 {:?}", path, e))
+    })
+}
+
+///
+/// 加载命令行参数指定的发音人预设，文件修改后会自动重新加载
+pub fn init_voice_preset() {
+    let args = AppArgs::parse_macro();
+    let path = match &args.voice_preset_file {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    match load_voice_preset(&path) {
+        Ok(presets) => {
+            info!("已加载 {} 个发音人预设 {}", presets.len(), path);
+            *GLOBAL_VOICE_PRESETS.write().unwrap() = Arc::new(presets);
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    watch_file_modified(
+        path,
+        VOICE_PRESET_RELOAD_INTERVAL,
+        |path| match load_voice_preset(path) {
+            Ok(presets) => {
+                info!("发音人预设文件已修改，重新加载 {}", path);
+                *GLOBAL_VOICE_PRESETS.write().unwrap() = Arc::new(presets);
+            }
+            Err(e) => error!("重新加载发音人预设失败，继续使用原有预设 {}", e),
+        },
+    );
+}
+
+///
+/// 获取指定名称的预设
+pub fn get_voice_preset(name: &str) -> Option<VoicePreset> {
+    GLOBAL_VOICE_PRESETS.read().unwrap().get(name).cloned()
+}
+
+///
+/// 获取所有预设，按名称排序
+pub fn get_voice_preset_list() -> Arc<BTreeMap<String, VoicePreset>> {
+    GLOBAL_VOICE_PRESETS.read().unwrap().clone()
+}
@@ -1,7 +1,15 @@
-use std::fmt::{Debug};
+use std::{fmt::Debug, sync::Arc};
 
-use actix_web::{body::BoxBody, http::StatusCode, web, HttpRequest, HttpResponse};
-use fancy_regex::Regex;
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::{Bytes, BytesMut};
+use futures::{channel::mpsc::unbounded, StreamExt};
 use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use urlencoding::decode as url_decode;
@@ -9,11 +17,24 @@ use urlencoding::decode as url_decode;
 use crate::{
     error::TTSServerError,
     info,
-    ms_tts::MsTtsMsgResponse,
+    ms_tts::{register_stream, remove_stream, MsTtsMsgResponse},
     random_string,
-    utils::azure_api::{
-        AzureApiEdgeFree, AzureApiSpeakerList, AzureApiSubscribeToken, MsApiOrigin,
-        MsTtsMsgRequest, MS_TTS_QUALITY_LIST,
+    utils::{
+        audio::{audio_content_type, riff_header_len},
+        azure_api::{
+            AzureApiEdgeFree, AzureApiSpeakerList, AzureApiSubscribeToken, MsApiOrigin,
+            MsTtsMsgRequest, VoicesList, MS_TTS_QUALITY_LIST,
+        },
+        deserialize_number_or_string,
+        language::{detect_language, language_default_voice, split_by_language},
+        lexicon::LexiconEntry,
+        ssml::{
+            parse_prosody_contour, parse_prosody_pitch, parse_prosody_volume, validate_ssml,
+            SSML_PITCH_LEVELS, SSML_VOLUME_LEVELS,
+        },
+        subtitle::{generate_subtitle, SubtitleFormat},
+        text_rule::normalize_text,
+        voice_preset::get_voice_preset,
     },
     web::{
         entity::ApiBaseResponse, error::ControllerError, middleware::token_auth::AuthTokenValue,
@@ -21,24 +42,114 @@ use crate::{
     AppArgs,
 };
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct MsTtsMsgRequestJson {
     // 待生成文本
     pub text: String,
+    // 发音人预设名称，请求中未填写的参数使用预设中的值
+    pub preset: Option<String>,
     // 发音人
     pub informant: Option<String>,
     // 音频风格
     pub style: Option<String>,
+    // 角色扮演 (仅订阅接口部分发音人支持)
+    pub role: Option<String>,
+    // 风格强度 0.01 - 2，默认 1
+    #[serde(alias = "styledegree")]
+    pub style_degree: Option<f32>,
     // 语速
     pub rate: Option<f32>,
-    // 音调
-    pub pitch: Option<f32>,
+    // 音调，支持倍数 0-2、相对值 +10%、赫兹 200Hz、半音 +2st 以及预设等级 x-low - x-high
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
+    pub pitch: Option<String>,
+    // 音量，支持倍数 0-2、相对值 +10% 以及预设等级 silent - x-loud
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
+    pub volume: Option<String>,
+    // 音调曲线，如 (0%,+20Hz) (50%,-2st)
+    pub contour: Option<String>,
     // 音频格式
     pub quality: Option<String>,
+    // 是否返回单词边界事件
+    pub word_boundary: Option<bool>,
+    // 是否返回句子边界事件
+    pub sentence_boundary: Option<bool>,
+    // 字幕格式 srt, vtt, lrc (仅字幕接口使用)
+    pub subtitle_format: Option<String>,
+    // 是否使用流式响应，边合成边返回音频
+    pub stream: Option<bool>,
     /// 认证 Token
     pub token: Option<String>,
+    // 发音词典条目，优先于服务端词典，仅支持 json 请求体
+    pub phoneme_list: Option<Vec<LexiconEntry>>,
+    // 是否检测文本语种，未指定发音人时使用对应语种的发音人，混合语言的文本按语种分段使用不同发音人
+    pub detect_language: Option<bool>,
+    // 严格校验参数，参数不合法时返回 400 而不是使用默认值，未填写时使用 --strict-validation 的设置
+    pub strict: Option<bool>,
     // text_replace_list:Vec<String>,
-    // phoneme_list:Vec<String>
+}
+
+///
+/// 严格校验模式下不合法的请求参数
+#[derive(Serialize, Debug)]
+pub struct InvalidParam {
+    /// 参数名
+    pub field: &'static str,
+    /// 请求中的值
+    pub value: String,
+    /// 可用的值或取值范围
+    pub allowed: Vec<String>,
+}
+
+impl InvalidParam {
+    fn new(field: &'static str, value: impl ToString, allowed: Vec<String>) -> Self {
+        InvalidParam {
+            field,
+            value: value.to_string(),
+            allowed,
+        }
+    }
+}
+
+///
+/// 判断倍数形式的参数是否超出范围，其他形式的参数不判断
+pub(crate) fn is_number_out_of_range(value: &str, min: f32, max: f32) -> bool {
+    match value.trim().parse::<f32>() {
+        Ok(num) => !(min..=max).contains(&num),
+        Err(_) => false,
+    }
+}
+
+///
+/// 是否使用严格校验，请求中未填写时使用 `--strict-validation` 的设置
+fn is_strict_validation(strict: Option<bool>) -> bool {
+    strict.unwrap_or_else(|| AppArgs::parse_macro().strict_validation)
+}
+
+///
+/// 严格校验模式下校验音频格式，格式不被接口支持时返回不合法的参数
+fn check_ms_quality(api_name: MsApiOrigin, quality: Option<&String>) -> Option<InvalidParam> {
+    let quality = quality?;
+    if quality.trim() == get_ms_quality(api_name, Some(quality)) {
+        return None;
+    }
+    let allowed = match api_name {
+        MsApiOrigin::EdgeFree => vec![AzureApiEdgeFree::OUTPUT_FORMAT.to_owned()],
+        MsApiOrigin::Subscription => MS_TTS_QUALITY_LIST.map(String::from).to_vec(),
+    };
+    Some(InvalidParam::new("quality", quality, allowed))
+}
+
+///
+/// 生成参数不合法的错误，错误详情中列出所有不合法的参数
+fn invalid_param_error(invalid_list: &[InvalidParam]) -> ControllerError {
+    let fields: Vec<&str> = invalid_list.iter().map(|i| i.field).collect();
+    let err = ControllerError::from_http_status(
+        StatusCode::BAD_REQUEST,
+        format!("请求参数不合法: {}", fields.join(", ")),
+    )
+    .with_data(serde_json::to_value(invalid_list).unwrap_or_default());
+    warn!("{:?}", err);
+    err
 }
 
 impl MsTtsMsgRequestJson {
@@ -47,7 +158,58 @@ impl MsTtsMsgRequestJson {
         api_name: MsApiOrigin,
         request_id_value: String,
     ) -> Result<MsTtsMsgRequest, ControllerError> {
-        let args = AppArgs::parse_macro();
+        match self.with_preset()? {
+            Some(request) => request.build_ms_request(api_name, request_id_value).await,
+            None => self.build_ms_request(api_name, request_id_value).await,
+        }
+    }
+
+    ///
+    /// 合并发音人预设，请求中填写的参数优先，未使用预设时返回 None
+    pub fn with_preset(&self) -> Result<Option<Self>, ControllerError> {
+        let name = match self.preset.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => name,
+            _ => return Ok(None),
+        };
+        let preset = get_voice_preset(name).ok_or_else(|| {
+            let err = ControllerError::from_http_status(
+                StatusCode::BAD_REQUEST,
+                format!("发音人预设 {} 不存在", name),
+            );
+            warn!("{:?}", err);
+            err
+        })?;
+        Ok(Some(MsTtsMsgRequestJson {
+            preset: None,
+            informant: self.informant.clone().or(preset.informant),
+            style: self.style.clone().or(preset.style),
+            role: self.role.clone().or(preset.role),
+            style_degree: self.style_degree.or(preset.style_degree),
+            rate: self.rate.or(preset.rate),
+            pitch: self.pitch.clone().or(preset.pitch),
+            volume: self.volume.clone().or(preset.volume),
+            quality: self.quality.clone().or(preset.quality),
+            ..self.clone()
+        }))
+    }
+
+    async fn build_ms_request(
+        &self,
+        api_name: MsApiOrigin,
+        request_id_value: String,
+    ) -> Result<MsTtsMsgRequest, ControllerError> {
+        let ms_informant_list = get_ms_voices_list(api_name).await?;
+        self.build_ms_request_with_voices(api_name, &ms_informant_list, request_id_value)
+    }
+
+    ///
+    /// 使用指定的发音人列表校验参数并生成合成请求
+    pub(crate) fn build_ms_request_with_voices(
+        &self,
+        api_name: MsApiOrigin,
+        ms_informant_list: &VoicesList,
+        request_id_value: String,
+    ) -> Result<MsTtsMsgRequest, ControllerError> {
         let text_value: String = {
             let mut text_tmp1 = self.text.as_str().to_string();
             // url 解码
@@ -67,84 +229,44 @@ impl MsTtsMsgRequestJson {
                 return Err(ControllerError::new("文本为空"));
             }
 
-            // 转义符号
-            let result = Regex::new(r"<")
-                .unwrap()
-                .replace_all(&text_tmp2, "&lt;")
-                .to_string();
-            let result = Regex::new(r">")
-                .unwrap()
-                .replace_all(&result, "&gt;")
-                .to_string();
-
-            let result = Regex::new(r"？")
-                .unwrap()
-                .replace_all(&result, "? ")
-                .to_string();
-            let result = Regex::new(r"，")
-                .unwrap()
-                .replace_all(&result, ", ")
-                .to_string();
-            let result = Regex::new(r"。")
-                .unwrap()
-                .replace_all(&result, ". ")
-                .to_string();
-            let result = Regex::new(r"：")
-                .unwrap()
-                .replace_all(&result, ": ")
-                .to_string();
-            let result = Regex::new(r"；")
-                .unwrap()
-                .replace_all(&result, "; ")
-                .to_string();
-            let result = Regex::new(r"！")
-                .unwrap()
-                .replace_all(&result, "! ")
-                .to_string();
-
-            result
+            text_tmp2
         };
 
-        let ms_informant_list = match api_name {
-            MsApiOrigin::EdgeFree => {
-                if !args.close_edge_free_api {
-                    AzureApiEdgeFree::new().get_vices_list().await
-                } else {
-                    Err(TTSServerError::ProgramError(
-                        "未开启 ms-tts-edge 接口，请勿调用".to_owned(),
-                    ))
-                }
-            }
-            MsApiOrigin::Subscription => {
-                if !args.close_official_subscribe_api {
-                    AzureApiSubscribeToken::get_vices_mixed_list().await
-                } else {
-                    Err(TTSServerError::ProgramError(
-                        "未开启 ms-tts-subscribe 接口，请勿调用".to_owned(),
-                    ))
-                }
-            }
-        }
-        .map_err(|e| {
-            let err = ControllerError::new(format!("获取发音人数据错误 {:?}", e));
-            error!("{:?}", err);
-            err
-        })?;
-
         // let ms_tts_config = &MS_TTS_CONFIG.get().unwrap();
 
+        let is_detect_language = self.detect_language.unwrap_or(false);
+
+        // 严格模式下收集所有不合法的参数，统一返回
+        let is_strict = is_strict_validation(self.strict);
+        let mut invalid_list: Vec<InvalidParam> = Vec::new();
+
+        let is_informant_valid = match &self.informant {
+            Some(inf) => ms_informant_list.voices_name_list.contains(inf),
+            None => true,
+        };
+        if is_strict && !is_informant_valid {
+            let mut allowed: Vec<String> =
+                ms_informant_list.voices_name_list.iter().cloned().collect();
+            allowed.sort();
+            invalid_list.push(InvalidParam::new(
+                "informant",
+                self.informant.as_deref().unwrap_or_default(),
+                allowed,
+            ));
+        }
+
         let informant_value: String = {
-            let default = "zh-CN-XiaoxiaoNeural".to_owned();
+            let default = if is_detect_language {
+                detect_language(&text_value)
+                    .and_then(|language| language_default_voice(ms_informant_list, language))
+            } else {
+                None
+            }
+            .unwrap_or_else(|| "zh-CN-XiaoxiaoNeural".to_owned());
 
             match &self.informant {
-                Some(inf) => {
-                    if ms_informant_list.voices_name_list.contains(inf) {
-                        inf.to_string()
-                    } else {
-                        default
-                    }
-                }
-                None => default,
+                Some(inf) if is_informant_valid => inf.to_string(),
+                _ => default,
             }
         }
         .trim()
@@ -155,6 +277,12 @@ impl MsTtsMsgRequestJson {
             .get(&informant_value)
             .unwrap();
 
+        // 按发音人的语言执行文本规则，XML 转义在生成 SSML 时进行
+        let text_value: String = normalize_text(&text_value, informant_item.get_local());
+        if text_value.trim().is_empty() {
+            return Err(ControllerError::new("文本为空"));
+        }
+
         let style_value: String = {
             let default = "general".to_owned();
             if let Some(style) = &self.style {
@@ -176,6 +304,49 @@ impl MsTtsMsgRequestJson {
         .trim()
         .to_owned();
 
+        // 发音人不合法时无法判断风格及角色是否可用
+        if is_strict && is_informant_valid {
+            if let Some(style) = &self.style {
+                let style = style.trim().to_lowercase();
+                if !style.is_empty() && style != "general" && style != style_value {
+                    let mut allowed = vec!["general".to_owned()];
+                    allowed.extend(informant_item.get_style().unwrap_or_default());
+                    invalid_list.push(InvalidParam::new("style", style, allowed));
+                }
+            }
+        }
+
+        // 角色名称以发音人角色列表中的写法为准
+        let role_value: Option<String> = self.role.as_ref().and_then(|role| {
+            let role = role.trim();
+            informant_item
+                .get_role()?
+                .into_iter()
+                .find(|i| i.eq_ignore_ascii_case(role))
+        });
+        if let (true, true, Some(role), None) =
+            (is_strict, is_informant_valid, &self.role, &role_value)
+        {
+            if !role.trim().is_empty() {
+                let allowed = informant_item.get_role().unwrap_or_default();
+                invalid_list.push(InvalidParam::new("role", role, allowed));
+            }
+        }
+
+        // 风格强度仅在使用了非默认风格时生效
+        let style_degree_value: Option<String> = match &self.style_degree {
+            Some(degree) if style_value != "general" => {
+                if degree <= &0.01 {
+                    Some("0.01".to_owned())
+                } else if degree >= &2.0 {
+                    Some("2".to_owned())
+                } else {
+                    Some(format!("{:.2}", degree))
+                }
+            }
+            _ => None,
+        };
+
         let rate_value: String = {
             let default = "0".to_owned();
 
@@ -196,50 +367,242 @@ impl MsTtsMsgRequestJson {
         .trim()
         .to_owned();
 
-        let pitch_value: String = {
-            let default = "0".to_owned();
+        let pitch_value: String = self
+            .pitch
+            .as_deref()
+            .and_then(parse_prosody_pitch)
+            .unwrap_or_else(|| "+0%".to_owned());
+
+        let volume_value: Option<String> = self.volume.as_deref().and_then(parse_prosody_volume);
+
+        let contour_value: Option<String> = self.contour.as_deref().and_then(parse_prosody_contour);
+
+        let quality_value = get_ms_quality(api_name, self.quality.as_ref());
+
+        if is_strict {
+            let range = |min: f32, max: f32| vec![format!("{} - {}", min, max)];
+            if let Some(degree) = self.style_degree {
+                if !(0.01..=2.0).contains(&degree) {
+                    invalid_list.push(InvalidParam::new("style_degree", degree, range(0.01, 2.0)));
+                }
+            }
+            if let Some(rate) = self.rate {
+                if !(0.0..=3.0).contains(&rate) {
+                    invalid_list.push(InvalidParam::new("rate", rate, range(0.0, 3.0)));
+                }
+            }
             if let Some(pitch) = &self.pitch {
-                if pitch <= &0.0 {
-                    "-50".to_owned()
-                } else if pitch >= &2.0 {
-                    "50".to_owned()
-                } else {
-                    let tmp = 50.00 * pitch - 50.00;
-                    format!("{:.0}", tmp)
+                if parse_prosody_pitch(pitch).is_none() || is_number_out_of_range(pitch, 0.0, 2.0) {
+                    let mut allowed = range(0.0, 2.0);
+                    allowed.extend(["+10%", "200Hz", "+2st"].map(String::from));
+                    allowed.extend(SSML_PITCH_LEVELS.map(String::from));
+                    invalid_list.push(InvalidParam::new("pitch", pitch, allowed));
                 }
-            } else {
-                default
             }
-        }
-        .trim()
-        .to_owned();
-
-        let quality_value: String = {
-            let default = "audio-24khz-48kbitrate-mono-mp3".to_owned();
-            if let Some(quality) = &self.quality {
-                if MS_TTS_QUALITY_LIST.contains(&quality.as_str()) {
-                    quality.to_owned()
-                } else {
-                    default
+            if let Some(volume) = &self.volume {
+                if parse_prosody_volume(volume).is_none()
+                    || is_number_out_of_range(volume, 0.0, 2.0)
+                {
+                    let mut allowed = range(0.0, 2.0);
+                    allowed.push("+10%".to_owned());
+                    allowed.extend(SSML_VOLUME_LEVELS.map(String::from));
+                    invalid_list.push(InvalidParam::new("volume", volume, allowed));
+                }
+            }
+            if let Some(contour) = &self.contour {
+                if contour_value.is_none() {
+                    let allowed = vec!["(0%,+20Hz) (50%,-2st)".to_owned()];
+                    invalid_list.push(InvalidParam::new("contour", contour, allowed));
                 }
-            } else {
-                default
             }
+            invalid_list.extend(check_ms_quality(api_name, self.quality.as_ref()));
         }
-        .trim()
-        .to_owned();
 
-        Ok(MsTtsMsgRequest {
+        if !invalid_list.is_empty() {
+            return Err(invalid_param_error(&invalid_list));
+        }
+
+        let phoneme_list = self.phoneme_list.clone().unwrap_or_default();
+        for entry in phoneme_list.iter() {
+            entry.check().map_err(|e| {
+                let err = ControllerError::from_http_status(StatusCode::BAD_REQUEST, e.to_string());
+                warn!("{:?}", err);
+                err
+            })?;
+        }
+
+        let mut request = MsTtsMsgRequest {
             text: text_value,
             request_id: request_id_value,
             informant: informant_value,
             style: style_value,
+            role: role_value,
+            style_degree: style_degree_value,
             rate: rate_value,
             pitch: pitch_value,
+            volume: volume_value,
+            contour: contour_value,
             quality: quality_value,
             subscribe_key: None,
             region: None,
-        })
+            word_boundary: self.word_boundary.unwrap_or(false),
+            sentence_boundary: self.sentence_boundary.unwrap_or(false),
+            stream: self.stream.unwrap_or(false),
+            is_retry: false,
+            ssml: None,
+            phoneme_list,
+            segments: Vec::new(),
+        };
+
+        if is_detect_language {
+            let segment_list = split_by_language(&request.text);
+            if segment_list.len() > 1 {
+                let informant_language = informant_item
+                    .get_local()
+                    .split('-')
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase();
+                request.segments = segment_list
+                    .into_iter()
+                    .map(|(language, text)| {
+                        let voice = if language == informant_language {
+                            None
+                        } else {
+                            language_default_voice(ms_informant_list, language)
+                        };
+                        match voice {
+                            // 其他语种的发音人不一定支持当前的风格及角色
+                            Some(voice) if voice != request.informant => MsTtsMsgRequest {
+                                text,
+                                request_id: random_string(32),
+                                informant: voice,
+                                style: "general".to_owned(),
+                                role: None,
+                                style_degree: None,
+                                ..request.clone()
+                            },
+                            _ => MsTtsMsgRequest {
+                                text,
+                                request_id: random_string(32),
+                                ..request.clone()
+                            },
+                        }
+                    })
+                    .collect();
+                debug!(
+                    "请求 {} 按语种切分为 {} 段",
+                    request.request_id,
+                    request.segments.len()
+                );
+            }
+        }
+
+        Ok(request)
+    }
+
+    ///
+    /// 请求中无法被接口支持而被忽略的参数，生成 http Warning 响应头
+    pub fn unsupported_warning(
+        &self,
+        api_name: MsApiOrigin,
+        request: &Result<MsTtsMsgRequest, ControllerError>,
+    ) -> Option<HeaderValue> {
+        let request = request.as_ref().ok()?;
+        let preset = self.with_preset().ok().flatten();
+        let this = preset.as_ref().unwrap_or(self);
+        let mut warning_list = Vec::new();
+        let style = this
+            .style
+            .as_deref()
+            .map(|i| i.trim().to_lowercase())
+            .filter(|i| !i.is_empty() && i != "general");
+        if let Some(style) = style {
+            match api_name {
+                MsApiOrigin::EdgeFree => warning_list.push(format!(
+                    "299 - \"style '{}' is not supported by the edge api and was ignored\"",
+                    style
+                )),
+                MsApiOrigin::Subscription if request.style != style => warning_list.push(format!(
+                    "299 - \"style '{}' is not supported by voice '{}' and was ignored\"",
+                    style, request.informant
+                )),
+                MsApiOrigin::Subscription => {}
+            }
+        }
+        let role = this
+            .role
+            .as_deref()
+            .map(|i| i.trim())
+            .filter(|i| !i.is_empty());
+        if let Some(role) = role {
+            match api_name {
+                MsApiOrigin::EdgeFree => warning_list.push(format!(
+                    "299 - \"role '{}' is not supported by the edge api and was ignored\"",
+                    role
+                )),
+                MsApiOrigin::Subscription if request.role.is_none() => warning_list.push(format!(
+                    "299 - \"role '{}' is not supported by voice '{}' and was ignored\"",
+                    role, request.informant
+                )),
+                MsApiOrigin::Subscription => {}
+            }
+        }
+        if warning_list.is_empty() {
+            return None;
+        }
+        let msg = warning_list.join(", ");
+        warn!("请求参数不受支持: {}", msg);
+        HeaderValue::from_str(&msg).ok()
+    }
+}
+
+///
+/// 获取接口的发音人列表
+async fn get_ms_voices_list(api_name: MsApiOrigin) -> Result<VoicesList, ControllerError> {
+    let args = AppArgs::parse_macro();
+    match api_name {
+        MsApiOrigin::EdgeFree => {
+            if !args.close_edge_free_api {
+                AzureApiEdgeFree::new().get_vices_list().await
+            } else {
+                Err(TTSServerError::ProgramError(
+                    "未开启 ms-tts-edge 接口，请勿调用".to_owned(),
+                ))
+            }
+        }
+        MsApiOrigin::Subscription => {
+            if !args.close_official_subscribe_api {
+                AzureApiSubscribeToken::get_vices_mixed_list().await
+            } else {
+                Err(TTSServerError::ProgramError(
+                    "未开启 ms-tts-subscribe 接口，请勿调用".to_owned(),
+                ))
+            }
+        }
+    }
+    .map_err(|e| {
+        let err = ControllerError::new(format!("获取发音人数据错误 {:?}", e));
+        error!("{:?}", err);
+        err
+    })
+}
+
+///
+/// 获取请求的音频格式，不支持的格式使用默认格式
+fn get_ms_quality(api_name: MsApiOrigin, quality: Option<&String>) -> String {
+    let default = "audio-24khz-48kbitrate-mono-mp3".to_owned();
+    if let MsApiOrigin::EdgeFree = api_name {
+        // edge 接口的音频格式是固定的
+        AzureApiEdgeFree::OUTPUT_FORMAT.to_owned()
+    } else if let Some(quality) = quality {
+        if MS_TTS_QUALITY_LIST.contains(&quality.as_str()) {
+            quality.trim().to_owned()
+        } else {
+            default
+        }
+    } else {
+        default
     }
 }
 
@@ -253,6 +616,155 @@ impl AuthTokenValue for MsTtsMsgRequestJson {
     }
 }
 
+///
+/// SSML 合成接口的请求参数
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MsTtsSsmlRequestJson {
+    // 完整的 SSML 文档
+    pub ssml: String,
+    // 音频格式
+    pub quality: Option<String>,
+    // 是否使用流式响应
+    pub stream: Option<bool>,
+    // 严格校验参数，未填写时使用 --strict-validation 的设置
+    pub strict: Option<bool>,
+    // 认证 token
+    pub token: Option<String>,
+}
+
+impl MsTtsSsmlRequestJson {
+    ///
+    /// 校验 SSML 文档并转换为合成请求
+    pub async fn to_ms_request(
+        &self,
+        api_name: MsApiOrigin,
+        request_id_value: String,
+    ) -> Result<MsTtsMsgRequest, ControllerError> {
+        let ms_informant_list = get_ms_voices_list(api_name).await?;
+        self.build_ms_request_with_voices(api_name, &ms_informant_list, request_id_value)
+    }
+
+    ///
+    /// 使用指定的发音人列表校验 SSML 文档并生成合成请求
+    pub(crate) fn build_ms_request_with_voices(
+        &self,
+        api_name: MsApiOrigin,
+        ms_informant_list: &VoicesList,
+        request_id_value: String,
+    ) -> Result<MsTtsMsgRequest, ControllerError> {
+        let voices =
+            validate_ssml(&self.ssml, &ms_informant_list.voices_name_list).map_err(|e| {
+                let err = ControllerError::from_http_status(StatusCode::BAD_REQUEST, e.to_string());
+                warn!("{:?}", err);
+                err
+            })?;
+        if voices.len() > 1 && !api_name.is_multi_voice() {
+            let err = ControllerError::from_http_status(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "该接口不支持在 SSML 中使用多个发音人: {}",
+                    voices.join(", ")
+                ),
+            );
+            warn!("{:?}", err);
+            return Err(err);
+        }
+        if is_strict_validation(self.strict) {
+            if let Some(invalid) = check_ms_quality(api_name, self.quality.as_ref()) {
+                return Err(invalid_param_error(&[invalid]));
+            }
+        }
+
+        Ok(MsTtsMsgRequest {
+            text: String::new(),
+            request_id: request_id_value,
+            informant: voices.into_iter().next().unwrap_or_default(),
+            style: "general".to_owned(),
+            role: None,
+            style_degree: None,
+            rate: "0".to_owned(),
+            pitch: "+0%".to_owned(),
+            volume: None,
+            contour: None,
+            quality: get_ms_quality(api_name, self.quality.as_ref()),
+            subscribe_key: None,
+            region: None,
+            word_boundary: false,
+            sentence_boundary: false,
+            stream: self.stream.unwrap_or(false),
+            is_retry: false,
+            ssml: Some(self.ssml.trim().to_owned()),
+            phoneme_list: Vec::new(),
+            segments: Vec::new(),
+        })
+    }
+}
+
+impl AuthTokenValue for MsTtsSsmlRequestJson {
+    fn get_token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MsTtsDialogueRequestJson {
+    // 按顺序排列的对话片段，每个片段可单独设置发音人、风格、语速、音调，音频格式及流式响应以外层为准
+    pub segments: Vec<MsTtsMsgRequestJson>,
+    // 音频格式
+    pub quality: Option<String>,
+    // 是否使用流式响应
+    pub stream: Option<bool>,
+    // 严格校验外层参数，未填写时使用 --strict-validation 的设置，对话片段的参数以片段中的设置为准
+    pub strict: Option<bool>,
+    // 认证 token
+    pub token: Option<String>,
+}
+
+impl MsTtsDialogueRequestJson {
+    ///
+    /// 逐个解析对话片段，跳过文本为空的片段
+    pub async fn to_ms_request_list(
+        &self,
+        api_name: MsApiOrigin,
+    ) -> Result<Vec<MsTtsMsgRequest>, ControllerError> {
+        if self.segments.is_empty() {
+            return Err(ControllerError::from_http_status(
+                StatusCode::BAD_REQUEST,
+                "对话片段不能为空",
+            ));
+        }
+        if is_strict_validation(self.strict) {
+            if let Some(invalid) = check_ms_quality(api_name, self.quality.as_ref()) {
+                return Err(invalid_param_error(&[invalid]));
+            }
+        }
+        let quality = get_ms_quality(api_name, self.quality.as_ref());
+        let mut list = Vec::with_capacity(self.segments.len());
+        for segment in self.segments.iter() {
+            match segment.to_ms_request(api_name, random_string(32)).await {
+                Ok(rd) => {
+                    for mut segment in rd.into_segments() {
+                        segment.quality = quality.clone();
+                        segment.stream = self.stream.unwrap_or(false);
+                        segment.word_boundary = false;
+                        segment.sentence_boundary = false;
+                        list.push(segment);
+                    }
+                }
+                Err(e) if e.msg == "文本为空" => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(list)
+    }
+}
+
+impl AuthTokenValue for MsTtsDialogueRequestJson {
+    fn get_token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
 /// 监听
 pub(crate) async fn tts_ms_post_controller(
     _req: HttpRequest,
@@ -261,8 +773,15 @@ pub(crate) async fn tts_ms_post_controller(
     let id = random_string(32);
     debug!("收到 post 请求{:?}", body);
     let request_tmp = body.to_ms_request(MsApiOrigin::EdgeFree, id.clone()).await;
+    let warning = body.unsupported_warning(MsApiOrigin::EdgeFree, &request_tmp);
     info!("解析 post 请求 {:?}", request_tmp);
-    let re = request_ms_tts("tts_ms_edge_free", request_tmp).await;
+    let re = request_ms_tts(
+        "tts_ms_edge_free",
+        MsApiOrigin::EdgeFree,
+        request_tmp,
+        warning,
+    )
+    .await;
     debug!("响应 post 请求 {}", &id);
     re
 }
@@ -276,9 +795,16 @@ pub(crate) async fn tts_ms_get_controller(
     let request_tmp = request
         .to_ms_request(MsApiOrigin::EdgeFree, id.clone())
         .await;
+    let warning = request.unsupported_warning(MsApiOrigin::EdgeFree, &request_tmp);
     info!("解析 get 请求 {:?}", request_tmp);
 
-    let re = request_ms_tts("tts_ms_edge_free", request_tmp).await;
+    let re = request_ms_tts(
+        "tts_ms_edge_free",
+        MsApiOrigin::EdgeFree,
+        request_tmp,
+        warning,
+    )
+    .await;
     debug!("响应 get 请求 {}", &id);
 
     re
@@ -293,8 +819,15 @@ pub(crate) async fn tts_ms_subscribe_api_get_controller(
     let request_tmp = request
         .to_ms_request(MsApiOrigin::Subscription, id.clone())
         .await;
+    let warning = request.unsupported_warning(MsApiOrigin::Subscription, &request_tmp);
     info!("解析 get 请求 {:?}", request_tmp);
-    let re = request_ms_tts("tts_ms_subscribe_api", request_tmp).await;
+    let re = request_ms_tts(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        request_tmp,
+        warning,
+    )
+    .await;
     debug!("响应 get 请求 {}", &id);
     re
 }
@@ -308,40 +841,317 @@ pub(crate) async fn tts_ms_subscribe_api_post_controller(
     let request_tmp = body
         .to_ms_request(MsApiOrigin::Subscription, id.clone())
         .await;
+    let warning = body.unsupported_warning(MsApiOrigin::Subscription, &request_tmp);
     info!("解析 post 请求 /api/tts-ms-subscribe {:?}", request_tmp);
-    let re = request_ms_tts("tts_ms_subscribe_api", request_tmp).await;
+    let re = request_ms_tts(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        request_tmp,
+        warning,
+    )
+    .await;
     debug!("响应 post 请求 {}", &id);
     re
 }
 
-async fn request_ms_tts(
+pub(crate) async fn tts_ms_subtitle_get_controller(
+    _req: HttpRequest,
+    request: web::Query<MsTtsMsgRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 get 请求 /api/tts-ms-edge/subtitle {:?}", request);
+    let re = request_ms_tts_subtitle(
+        "tts_ms_edge_free",
+        MsApiOrigin::EdgeFree,
+        &request,
+        id.clone(),
+    )
+    .await;
+    debug!("响应 get 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_subtitle_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsMsgRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-edge/subtitle {:?}", body);
+    let re =
+        request_ms_tts_subtitle("tts_ms_edge_free", MsApiOrigin::EdgeFree, &body, id.clone()).await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_subscribe_api_subtitle_get_controller(
+    _req: HttpRequest,
+    request: web::Query<MsTtsMsgRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 get 请求 /api/tts-ms-subscribe/subtitle {:?}", request);
+    let re = request_ms_tts_subtitle(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        &request,
+        id.clone(),
+    )
+    .await;
+    debug!("响应 get 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_subscribe_api_subtitle_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsMsgRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-subscribe/subtitle {:?}", body);
+    let re = request_ms_tts_subtitle(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        &body,
+        id.clone(),
+    )
+    .await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_ssml_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsSsmlRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-edge/ssml {:?}", body);
+    let request_tmp = body.to_ms_request(MsApiOrigin::EdgeFree, id.clone()).await;
+    info!("解析 post 请求 /api/tts-ms-edge/ssml {:?}", request_tmp);
+    let re = request_ms_tts("tts_ms_edge_free", MsApiOrigin::EdgeFree, request_tmp, None).await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_subscribe_api_ssml_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsSsmlRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-subscribe/ssml {:?}", body);
+    let request_tmp = body
+        .to_ms_request(MsApiOrigin::Subscription, id.clone())
+        .await;
+    info!(
+        "解析 post 请求 /api/tts-ms-subscribe/ssml {:?}",
+        request_tmp
+    );
+    let re = request_ms_tts(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        request_tmp,
+        None,
+    )
+    .await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_dialogue_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsDialogueRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-edge/dialogue {:?}", body);
+    let re =
+        request_ms_tts_dialogue("tts_ms_edge_free", MsApiOrigin::EdgeFree, &body, id.clone()).await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+pub(crate) async fn tts_ms_subscribe_api_dialogue_post_controller(
+    _req: HttpRequest,
+    body: web::Json<MsTtsDialogueRequestJson>,
+) -> Result<HttpResponse, ControllerError> {
+    let id = random_string(32);
+    debug!("收到 post 请求 /api/tts-ms-subscribe/dialogue {:?}", body);
+    let re = request_ms_tts_dialogue(
+        "tts_ms_subscribe_api",
+        MsApiOrigin::Subscription,
+        &body,
+        id.clone(),
+    )
+    .await;
+    debug!("响应 post 请求 {}", &id);
+    re
+}
+
+/// 长文本分段后同时进行合成的最大段数
+const MS_TTS_CHUNK_CONCURRENCY: usize = 3;
+
+///
+/// 合成语音，长文本会分段合成后按顺序拼接
+async fn synthesize_ms_tts(
+    api_name: &str,
+    api_origin: MsApiOrigin,
+    rd: MsTtsMsgRequest,
+) -> Result<MsTtsMsgResponse, ControllerError> {
+    let id = rd.request_id.clone();
+    let quality = rd.quality.clone();
+    let args = AppArgs::parse_macro();
+    let chunk_list = rd.split_chunks(api_origin.is_multi_voice(), args.text_chunk_len);
+    synthesize_ms_tts_chunks(api_name, id, &quality, chunk_list).await
+}
+
+///
+/// 按顺序合成多段请求并拼接音频
+async fn synthesize_ms_tts_chunks(
     api_name: &str,
+    id: String,
+    quality: &str,
+    chunk_list: Vec<MsTtsMsgRequest>,
+) -> Result<MsTtsMsgResponse, ControllerError> {
+    if chunk_list.len() > 1 {
+        debug!("长文本切分为 {} 段进行合成 {}", chunk_list.len(), &id);
+    }
+    // debug!("请求微软语音服务器");
+    let resp_list = futures::stream::iter(chunk_list)
+        .map(|chunk| crate::GLOBAL_EB.request(api_name, chunk.into()))
+        .buffered(MS_TTS_CHUNK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    // debug!("请求微软语音完成");
+    let list = resp_list
+        .into_iter()
+        .map(|i| check_ms_tts_response(&id, i))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(MsTtsMsgResponse::concat(id, quality, list))
+}
+
+///
+/// 解析事件总线的响应，合成失败时转换为对应的错误
+pub(crate) fn check_ms_tts_response(
+    id: &str,
+    resp: Option<Arc<event_bus::message::Body>>,
+) -> Result<MsTtsMsgResponse, ControllerError> {
+    let data = match resp {
+        Some(data) => MsTtsMsgResponse::from_vec(data.as_bytes().unwrap().to_vec()),
+        None => {
+            warn!("生成语音失败 {}", id);
+            return Err(ControllerError::new("未知错误"));
+        }
+    };
+    match data.error {
+        Some(e) => {
+            warn!("生成语音失败 {}: {:?}", id, e);
+            Err(e.into())
+        }
+        None => Ok(data),
+    }
+}
+
+///
+/// 流式合成语音，音频数据收到后直接返回，长文本会按顺序逐段合成
+async fn stream_ms_tts(
+    api_name: &'static str,
+    api_origin: MsApiOrigin,
+    rd: MsTtsMsgRequest,
+) -> HttpResponse {
+    let id = rd.request_id.clone();
+    let quality = rd.quality.clone();
+    let args = AppArgs::parse_macro();
+    let chunk_list = rd.split_chunks(api_origin.is_multi_voice(), args.text_chunk_len);
+    stream_ms_tts_chunks(api_name, id, quality, chunk_list)
+}
+
+///
+/// 按顺序流式合成多段请求
+fn stream_ms_tts_chunks(
+    api_name: &'static str,
+    id: String,
+    quality: String,
+    chunk_list: Vec<MsTtsMsgRequest>,
+) -> HttpResponse {
+    let content_type = audio_content_type(&quality);
+    let (tx, rx) = unbounded::<Result<Bytes, TTSServerError>>();
+
+    actix_web::rt::spawn(async move {
+        for (index, chunk) in chunk_list.into_iter().enumerate() {
+            let chunk_id = chunk.request_id.clone();
+            let mut audio_rx = register_stream(&chunk_id).await;
+            let reply = async {
+                let resp = crate::GLOBAL_EB.request(api_name, chunk.into()).await;
+                // 请求失败时通道可能未被领取，需要手动移除
+                remove_stream(&chunk_id).await;
+                resp
+            };
+            let forward = async {
+                // wav 格式除第一段外需要去掉文件头
+                let mut riff_head = if index > 0 && quality.starts_with("riff-") {
+                    Some(BytesMut::new())
+                } else {
+                    None
+                };
+                while let Some(data) = audio_rx.next().await {
+                    let data = match riff_head.as_mut() {
+                        Some(head) => {
+                            head.extend_from_slice(&data);
+                            match riff_header_len(head) {
+                                Some(len) => {
+                                    let data = head.split_off(len).freeze();
+                                    riff_head = None;
+                                    data
+                                }
+                                None => continue,
+                            }
+                        }
+                        None => data,
+                    };
+                    if tx.unbounded_send(Ok(data)).is_err() {
+                        debug!("客户端已断开流式响应 {}", &id);
+                        return true;
+                    }
+                }
+                false
+            };
+            let (resp, disconnected) = futures::join!(reply, forward);
+            // 客户端已断开，不再合成剩余分段
+            if disconnected {
+                break;
+            }
+            if let Err(e) = check_ms_tts_response(&chunk_id, resp) {
+                let _ = tx.unbounded_send(Err(TTSServerError::ThirdPartyApiCallFailed(e.msg)));
+                break;
+            }
+        }
+        debug!("流式响应结束 {}", &id);
+    });
+
+    HttpResponse::build(StatusCode::OK)
+        .content_type(content_type)
+        .streaming(rx)
+}
+
+async fn request_ms_tts(
+    api_name: &'static str,
+    api_origin: MsApiOrigin,
+    data: Result<MsTtsMsgRequest, ControllerError>,
+    warning: Option<HeaderValue>,
+) -> Result<HttpResponse, ControllerError> {
+    let mut respone = request_ms_tts_audio(api_name, api_origin, data).await?;
+    if let Some(warning) = warning {
+        respone.headers_mut().insert(header::WARNING, warning);
+    }
+    Ok(respone)
+}
+
+async fn request_ms_tts_audio(
+    api_name: &'static str,
+    api_origin: MsApiOrigin,
     data: Result<MsTtsMsgRequest, ControllerError>,
 ) -> Result<HttpResponse, ControllerError> {
     match data {
+        Ok(rd) if rd.stream => Ok(stream_ms_tts(api_name, api_origin, rd).await),
         Ok(rd) => {
-            let id = rd.request_id.clone();
-            // debug!("请求微软语音服务器");
-            let kk = crate::GLOBAL_EB.request(api_name, rd.into()).await;
-            // debug!("请求微软语音完成");
-            match kk {
-                Some(data) => {
-                    let data = MsTtsMsgResponse::from_vec(data.as_bytes().unwrap().to_vec());
-
-                    let mut respone = HttpResponse::build(StatusCode::OK).body(data.data);
-                    respone.headers_mut().insert(
-                        actix_web::http::header::CONTENT_TYPE,
-                        data.file_type.parse().unwrap(),
-                    );
-                    Ok(respone)
-                }
-                None => {
-                    warn!("生成语音失败 {}", id);
-
-                    let ll: HttpResponse<BoxBody> = ApiBaseResponse::<()>::error("未知错误").into();
-                    Ok(ll)
-                }
-            }
+            let data = synthesize_ms_tts(api_name, api_origin, rd).await?;
+            Ok(ms_tts_audio_response(data))
         }
         Err(e) => {
             if e.msg == "文本为空" {
@@ -360,3 +1170,131 @@ async fn request_ms_tts(
         }
     }
 }
+
+///
+/// 音频响应
+fn ms_tts_audio_response(data: MsTtsMsgResponse) -> HttpResponse {
+    let mut respone = HttpResponse::build(StatusCode::OK).body(data.data);
+    respone.headers_mut().insert(
+        actix_web::http::header::CONTENT_TYPE,
+        data.file_type.parse().unwrap(),
+    );
+    respone
+}
+
+///
+/// 合成多人对话，subscribe 接口将片段合并为包含多个 voice 元素的 SSML 文档，
+/// edge 接口不支持多个 voice 元素，每个片段单独合成，最后按顺序拼接音频
+async fn request_ms_tts_dialogue(
+    api_name: &'static str,
+    api_origin: MsApiOrigin,
+    request: &MsTtsDialogueRequestJson,
+    request_id: String,
+) -> Result<HttpResponse, ControllerError> {
+    let list = request.to_ms_request_list(api_origin).await?;
+    let quality = match list.first() {
+        Some(first) => first.quality.clone(),
+        None => {
+            return request_ms_tts(
+                api_name,
+                api_origin,
+                Err(ControllerError::new("文本为空")),
+                None,
+            )
+            .await
+        }
+    };
+    info!("解析对话请求 {} 共 {} 个片段", request_id, list.len());
+
+    let args = AppArgs::parse_macro();
+    let chunk_list = if api_origin.is_multi_voice() {
+        MsTtsMsgRequest::merge_dialogue(list, args.text_chunk_len)
+    } else {
+        list.into_iter()
+            .flat_map(|i| i.split_by_text_len(args.text_chunk_len))
+            .collect()
+    };
+    if request.stream.unwrap_or(false) {
+        Ok(stream_ms_tts_chunks(
+            api_name, request_id, quality, chunk_list,
+        ))
+    } else {
+        let data = synthesize_ms_tts_chunks(api_name, request_id, &quality, chunk_list).await?;
+        Ok(ms_tts_audio_response(data))
+    }
+}
+
+/// 字幕接口响应
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MsTtsSubtitleResponse {
+    /// base64 编码的音频数据
+    pub audio: String,
+    /// 音频格式
+    pub file_type: String,
+    /// 字幕格式
+    pub subtitle_format: String,
+    /// 字幕文件类型
+    pub subtitle_type: String,
+    /// 字幕内容
+    pub subtitle: String,
+}
+
+///
+/// 合成语音并根据边界事件生成同步字幕
+async fn request_ms_tts_subtitle(
+    api_name: &str,
+    api_origin: MsApiOrigin,
+    request: &MsTtsMsgRequestJson,
+    request_id: String,
+) -> Result<HttpResponse, ControllerError> {
+    let format_str = request.subtitle_format.as_deref().unwrap_or("srt");
+    let format = SubtitleFormat::try_from(format_str).map_err(|e| {
+        let err = ControllerError::new(format!("{}", e));
+        error!("{:?}", err);
+        err
+    })?;
+
+    let request_tmp = request.to_ms_request(api_origin, request_id).await;
+    let warning = request.unsupported_warning(api_origin, &request_tmp);
+    let data = match request_tmp {
+        Ok(mut rd) => {
+            let subtitle_request = |i: &mut MsTtsMsgRequest| {
+                i.word_boundary = true;
+                i.sentence_boundary = true;
+                i.stream = false;
+            };
+            rd.segments.iter_mut().for_each(subtitle_request);
+            subtitle_request(&mut rd);
+            info!("解析字幕请求 {:?}", rd);
+            synthesize_ms_tts(api_name, api_origin, rd).await?
+        }
+        Err(e) => {
+            if e.msg == "文本为空" {
+                warn!("请求文本为空");
+                MsTtsMsgResponse {
+                    request_id: "".to_owned(),
+                    data: crate::ms_tts::BLANK_MUSIC_FILE.to_vec(),
+                    file_type: "audio/mpeg".to_owned(),
+                    boundaries: Vec::new(),
+                    error: None,
+                }
+            } else {
+                error!("调用错误：{:?}", e);
+                return Err(e);
+            }
+        }
+    };
+
+    let body = MsTtsSubtitleResponse {
+        audio: general_purpose::STANDARD.encode(&data.data),
+        file_type: data.file_type,
+        subtitle_format: format_str.to_lowercase(),
+        subtitle_type: format.content_type().to_owned(),
+        subtitle: generate_subtitle(format, &data.boundaries),
+    };
+    let mut respone: HttpResponse = ApiBaseResponse::success(Some(body)).into();
+    if let Some(warning) = warning {
+        respone.headers_mut().insert(header::WARNING, warning);
+    }
+    Ok(respone)
+}
@@ -1,13 +1,17 @@
 use std::fmt::{Display, Formatter};
 
-use actix_web::{error, HttpResponse};
+use actix_web::{error, http::StatusCode, HttpResponse};
 
-use crate::web::entity::ApiBaseResponse;
+use crate::{ms_tts::MsTtsMsgError, web::entity::ApiBaseResponse};
 
 #[derive(Debug)]
 pub struct ControllerError {
     pub code: i32,
     pub msg: String,
+    /// 响应的 http 状态码，默认为 200，错误码在响应体的 code 字段中返回
+    pub http_status: StatusCode,
+    /// 错误详情，在响应体的 data 字段中返回
+    pub data: Option<serde_json::Value>,
 }
 
 impl ControllerError {
@@ -15,12 +19,61 @@ impl ControllerError {
         ControllerError {
             code: 500,
             msg: msg.into(),
+            http_status: StatusCode::OK,
+            data: None,
         }
     }
     pub fn from_status_code<T: Into<String>>(code: i32, msg: T) -> Self {
         ControllerError {
             code,
             msg: msg.into(),
+            http_status: StatusCode::OK,
+            data: None,
+        }
+    }
+    /// 同时设置 http 状态码以及响应体中的 code
+    pub fn from_http_status<T: Into<String>>(status: StatusCode, msg: T) -> Self {
+        ControllerError {
+            code: status.as_u16() as i32,
+            msg: msg.into(),
+            http_status: status,
+            data: None,
+        }
+    }
+    /// 附加错误详情
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl From<MsTtsMsgError> for ControllerError {
+    fn from(value: MsTtsMsgError) -> Self {
+        match value {
+            MsTtsMsgError::Timeout => ControllerError::from_http_status(
+                StatusCode::GATEWAY_TIMEOUT,
+                "等待微软接口响应超时",
+            ),
+            MsTtsMsgError::Disconnected => ControllerError::from_http_status(
+                StatusCode::BAD_GATEWAY,
+                "与微软接口的连接已断开，重试后仍然失败",
+            ),
+            MsTtsMsgError::Interrupted => ControllerError::from_http_status(
+                StatusCode::BAD_GATEWAY,
+                "与微软接口的连接已断开，音频已部分输出，无法重试",
+            ),
+            MsTtsMsgError::Unavailable => ControllerError::from_http_status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "微软接口连接连续失败，暂时不可用，请稍后重试",
+            ),
+            MsTtsMsgError::ConnectFailed(e) => ControllerError::from_http_status(
+                StatusCode::BAD_GATEWAY,
+                format!("连接微软接口失败 {}", e),
+            ),
+            MsTtsMsgError::QuotaExceeded => ControllerError::from_http_status(
+                StatusCode::TOO_MANY_REQUESTS,
+                "所有订阅key本月剩余的字符额度都不足",
+            ),
         }
     }
 }
@@ -38,6 +91,11 @@ impl Display for ControllerError {
 // Use default implementation for `error_response()` method
 impl error::ResponseError for ControllerError {
     fn error_response(&self) -> HttpResponse {
-        ApiBaseResponse::<()>::error_by_status_code(self.code, &self.msg).into()
+        let mut body =
+            ApiBaseResponse::<serde_json::Value>::error_by_status_code(self.code, &self.msg);
+        body.data = self.data.clone();
+        let mut response: HttpResponse = body.into();
+        *response.status_mut() = self.http_status;
+        response
     }
 }
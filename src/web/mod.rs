@@ -49,6 +49,37 @@ pub(crate) async fn register_service() {
         );
         // }
 
+        app = app.service(
+            // 订阅接口 音频及同步字幕
+            web::resource("/api/tts-ms-subscribe/subtitle")
+                .wrap(Condition::new(
+                    args.subscribe_api_auth_token.is_some(),
+                    TokenAuthentication::<MsTtsMsgRequestJson>::default(),
+                ))
+                .route(web::get().to(tts_ms_subscribe_api_subtitle_get_controller))
+                .route(web::post().to(tts_ms_subscribe_api_subtitle_post_controller)),
+        );
+
+        app = app.service(
+            // 订阅接口 SSML 文档直接合成
+            web::resource("/api/tts-ms-subscribe/ssml")
+                .wrap(Condition::new(
+                    args.subscribe_api_auth_token.is_some(),
+                    TokenAuthentication::<MsTtsSsmlRequestJson>::default(),
+                ))
+                .route(web::post().to(tts_ms_subscribe_api_ssml_post_controller)),
+        );
+
+        app = app.service(
+            // 订阅接口 多人对话合成
+            web::resource("/api/tts-ms-subscribe/dialogue")
+                .wrap(Condition::new(
+                    args.subscribe_api_auth_token.is_some(),
+                    TokenAuthentication::<MsTtsDialogueRequestJson>::default(),
+                ))
+                .route(web::post().to(tts_ms_subscribe_api_dialogue_post_controller)),
+        );
+
         // if !args.close_edge_free_api {
         app = app.service(
             // 旧版本 edge 预览接口
@@ -56,6 +87,22 @@ pub(crate) async fn register_service() {
                 .route(web::get().to(tts_ms_get_controller))
                 .route(web::post().to(tts_ms_post_controller)),
         );
+        app = app.service(
+            // edge 接口 音频及同步字幕
+            web::resource("/api/tts-ms-edge/subtitle")
+                .route(web::get().to(tts_ms_subtitle_get_controller))
+                .route(web::post().to(tts_ms_subtitle_post_controller)),
+        );
+        app = app.service(
+            // edge 接口 SSML 文档直接合成
+            web::resource("/api/tts-ms-edge/ssml")
+                .route(web::post().to(tts_ms_ssml_post_controller)),
+        );
+        app = app.service(
+            // edge 接口 多人对话合成
+            web::resource("/api/tts-ms-edge/dialogue")
+                .route(web::post().to(tts_ms_dialogue_post_controller)),
+        );
         // }
 
         // 根据功能
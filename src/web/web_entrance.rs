@@ -1,13 +1,18 @@
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Responder};
 use log::error;
 // use mime_guess::from_path;
 // use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    utils::azure_api::{
-        AzureApiEdgeFree, AzureApiSpeakerList, AzureApiSubscribeToken, MsApiOrigin,
-        MS_TTS_QUALITY_LIST,
+    utils::{
+        azure_api::{
+            AzureApiEdgeFree, AzureApiSpeakerList, AzureApiSubscribeToken, MsApiOrigin, Voice,
+            VoicesFilter, VoicesItem, MS_TTS_QUALITY_LIST,
+        },
+        voice_preset::get_voice_preset_list,
     },
     web::{entity::ApiBaseResponse, error::ControllerError, vo::BaseResponse},
     AppArgs,
@@ -26,11 +31,16 @@ pub(crate) fn register_router(cfg: &mut web::ServiceConfig) {
         web::resource("/api/ms-tts/style/{api_name}/{informant}")
             .route(web::get().to(get_ms_tts_style)),
     )
+    .service(
+        web::resource("/api/ms-tts/role/{api_name}/{informant}")
+            .route(web::get().to(get_ms_tts_role)),
+    )
     .route(
         "/api/ms-tts/informant/{api_name}",
         web::get().to(get_ms_tts_informant),
     )
-    .route("/api/ms-tts/quality", web::get().to(get_ms_tts_quality));
+    .route("/api/ms-tts/quality", web::get().to(get_ms_tts_quality))
+    .route("/api/ms-tts/preset", web::get().to(get_ms_tts_preset));
     // 等待web UI 适配
     // .service(web::resource("/").route(web::get().to(html_index)))
     // .service(web::resource("/{_:.*}").route(web::get().to(dist)));
@@ -98,8 +108,10 @@ pub enum ApiParam {
         param_name: String,
         param_desc: String,
         // #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(alias = "min_value")]
         float_min: Option<f32>,
         // #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(alias = "max_value")]
         float_max: Option<f32>,
         // #[serde(skip_serializing_if = "Option::is_none")]
         default_value: Option<f32>,
@@ -138,12 +150,23 @@ pub(crate) async fn get_api_list(_req: HttpRequest) -> Result<impl Responder, Co
     Ok(BaseResponse::from(api_list))
 }
 
+///
+/// 发音人列表的返回格式
+#[derive(Deserialize, Debug)]
+pub struct VoicesFormatParams {
+    /// 为 true 时返回接口原始的发音人数据，默认返回统一格式的数据
+    raw: Option<bool>,
+}
+
 ///
 /// /api/ms-tts/informant/{api_name}
 /// 获取微软文本转语音接口发音人列表
 ///
+/// 可通过 locale、language、gender、voice_type、style、role、name 参数筛选
 pub(crate) async fn get_ms_tts_informant(
     path_params: web::Path<String>,
+    filter: web::Query<VoicesFilter>,
+    format: web::Query<VoicesFormatParams>,
 ) -> Result<HttpResponse, ControllerError> {
     let api_name = MsApiOrigin::try_from(path_params.into_inner()).map_err(|e| {
         let err = format!("接口配置数据不存在 {:?}", e);
@@ -198,14 +221,16 @@ pub(crate) async fn get_ms_tts_informant(
         return Err(err);
     }
     let vices_list = vices_list.unwrap();
-    vices_list.voices_name_list.iter().for_each(|v| {
-        let voice_item = vices_list.by_voices_name_map.get(v).unwrap();
-
+    vices_list.filter(&filter).iter().for_each(|voice_item| {
         let desc = voice_item.get_desc();
-        let tmp = serde_json::to_value(voice_item.as_ref()).unwrap();
+        let tmp = if format.raw.unwrap_or(false) {
+            serde_json::to_value(voice_item.as_ref()).unwrap()
+        } else {
+            serde_json::to_value(Voice::from(voice_item.as_ref())).unwrap()
+        };
 
         list.push(ListDataItem {
-            key: v.clone(),
+            key: voice_item.get_short_name(),
             desc,
             data: tmp,
         });
@@ -234,6 +259,21 @@ pub(crate) async fn get_ms_tts_quality(_req: HttpRequest) -> Result<HttpResponse
     Ok(ApiBaseResponse::success(Some(list)).into())
 }
 
+///
+/// 获取发音人预设列表
+pub(crate) async fn get_ms_tts_preset(_req: HttpRequest) -> Result<HttpResponse, ControllerError> {
+    let list: Vec<ListDataItem> = get_voice_preset_list()
+        .iter()
+        .map(|(name, preset)| ListDataItem {
+            key: name.clone(),
+            desc: preset.desc.clone(),
+            data: serde_json::to_value(preset).unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(ApiBaseResponse::success(Some(list)).into())
+}
+
 //
 // #[get("/ms-tts/style/{informant}")]
 #[derive(Deserialize, Debug)]
@@ -245,7 +285,55 @@ pub(crate) struct PathParams {
 pub(crate) async fn get_ms_tts_style(
     path_params: web::Path<PathParams>,
 ) -> Result<HttpResponse, ControllerError> {
-    let params = path_params.into_inner();
+    let voice_item = get_voice_item(path_params.into_inner()).await?;
+
+    let mut list_data: Vec<ListDataItem> = Vec::new();
+    let vec_style = if voice_item.get_style().is_some() {
+        let mut ff = vec!["general".to_owned()];
+        let mut kk = voice_item.get_style().as_ref().unwrap().to_vec();
+        ff.append(&mut kk);
+        ff
+    } else {
+        let ff = vec!["general".to_owned()];
+        ff
+    };
+
+    vec_style.iter().for_each(|v| {
+        list_data.push(ListDataItem {
+            key: v.clone(),
+            desc: "".to_owned(),
+            data: serde_json::Value::Null,
+        });
+    });
+
+    Ok(ApiBaseResponse::success(Some(list_data)).into())
+}
+
+///
+/// /api/ms-tts/role/{api_name}/{informant}
+/// 获取发音人支持的角色扮演列表，不支持角色扮演时返回空列表
+pub(crate) async fn get_ms_tts_role(
+    path_params: web::Path<PathParams>,
+) -> Result<HttpResponse, ControllerError> {
+    let voice_item = get_voice_item(path_params.into_inner()).await?;
+
+    let list_data: Vec<ListDataItem> = voice_item
+        .get_role()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| ListDataItem {
+            key: v,
+            desc: "".to_owned(),
+            data: serde_json::Value::Null,
+        })
+        .collect();
+
+    Ok(ApiBaseResponse::success(Some(list_data)).into())
+}
+
+///
+/// 根据路径参数获取发音人数据
+async fn get_voice_item(params: PathParams) -> Result<Arc<VoicesItem>, ControllerError> {
     let informant = params.informant;
     let api_name = MsApiOrigin::try_from(params.api_name).map_err(|e| {
         let err = format!("接口配置数据不存在 {:?}", e);
@@ -301,28 +389,16 @@ pub(crate) async fn get_ms_tts_style(
     }
     let vices_list = vices_list.unwrap();
 
-    let mut list_data: Vec<ListDataItem> = Vec::new();
-    let voice_item = vices_list.by_voices_name_map.get(&informant).unwrap();
-    let vec_style = if voice_item.get_style().is_some() {
-        let mut ff = vec!["general".to_owned()];
-        let mut kk = voice_item
-            .get_style()
-            .as_ref()
-            .unwrap().to_vec();
-        ff.append(&mut kk);
-        ff
-    } else {
-        let ff = vec!["general".to_owned()];
-        ff
-    };
-
-    vec_style.iter().for_each(|v| {
-        list_data.push(ListDataItem {
-            key: v.clone(),
-            desc: "".to_owned(),
-            data: serde_json::Value::Null,
-        });
-    });
-
-    Ok(ApiBaseResponse::success(Some(list_data)).into())
+    vices_list
+        .by_voices_name_map
+        .get(&informant)
+        .cloned()
+        .ok_or_else(|| {
+            let err = ControllerError::from_http_status(
+                StatusCode::NOT_FOUND,
+                format!("发音人 {} 不存在", informant),
+            );
+            error!("{:?}", err);
+            err
+        })
 }